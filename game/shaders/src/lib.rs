@@ -1,11 +1,13 @@
 #![no_std]
-use spirv_std::spirv;
-use spirv_std::glam::{Vec3, Vec2, Vec4, Mat4};
+use spirv_std::{spirv, Image, Sampler};
+use spirv_std::glam::{Vec3, Vec2, Vec4, Mat4, Vec4Swizzles};
+#[cfg(target_arch = "spirv")]
 use spirv_std::num_traits::Float;
-use miau_shared::SceneConst;
+use miau_shared::{SceneConst, ShadowConst};
 use game_shared::FurConst;
 
 #[spirv(vertex)]
+#[allow(clippy::too_many_arguments)]
 pub fn main_v(
   pos: Vec3,
   uv: Vec2,
@@ -18,20 +20,29 @@ pub fn main_v(
   out_uv: &mut Vec2,
   out_normal: &mut Vec3,
   out_n: &mut u32,
+  out_world: &mut Vec3,
 ) {
   let layer = n as f32 / consts.layers as f32;
-  *out_pos = scene.cam * *transform * (pos + normal * layer * consts.height).extend(1.0);
+  let world = *transform * (pos + normal * layer * consts.height).extend(1.0);
+  *out_pos = scene.cam * world;
+  *out_world = world.xyz();
   *out_uv = uv;
   *out_normal = normal;
   *out_n = n;
 }
 
 #[spirv(fragment)]
+#[allow(clippy::too_many_arguments)]
 pub fn main_f(
   uv: Vec2,
   normal: Vec3,
   #[spirv(flat)] n: u32,
+  world: Vec3,
+  #[spirv(uniform, descriptor_set = 0, binding = 0)] scene: &SceneConst,
   #[spirv(uniform, descriptor_set = 1, binding = 0)] consts: &FurConst,
+  #[spirv(uniform, descriptor_set = 2, binding = 0)] shadow: &ShadowConst,
+  #[spirv(descriptor_set = 2, binding = 1)] shadow_map: &Image!(2D, type=f32, sampled, depth, arrayed),
+  #[spirv(descriptor_set = 2, binding = 2)] shadow_sampler: &Sampler,
   out_color: &mut Vec4,
 ) {
   let layer = n as f32 / consts.layers as f32;
@@ -44,7 +55,8 @@ pub fn main_f(
   }
   let color = (normal + 1.0) / 2.0;
   let ao = (0.1 + layer.powf(2.0)).min(1.0);
-  *out_color = (color * ao).extend(1.0);
+  let shadow = shadow.sample(shadow_map, shadow_sampler, world, scene.eye.xyz());
+  *out_color = (color * ao * (0.4 + 0.6 * shadow)).extend(1.0);
 }
 
 fn hash(x: Vec2) -> f32 {
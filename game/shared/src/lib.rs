@@ -1,6 +1,11 @@
 #![no_std]
+#[cfg(feature = "host")]
+use serde::{Serialize, Deserialize};
+#[cfg(feature = "host")]
+use miau::gfx::Bindable;
 
 #[repr(C)]
+#[cfg_attr(feature = "host", derive(Serialize, Deserialize, Bindable))]
 pub struct FurConst {
   pub layers: u32,
   pub density: f32,
@@ -1,124 +1,67 @@
 use std::mem;
+use std::rc::Rc;
 use miau::Result;
-use miau::ecs::{World, stage, component};
+use miau::ecs::{World, component};
 use miau::assets::{Assets, Handle};
 use miau::math::Mat4;
 use miau::gfx::{
-  Renderer, Mesh, Shader, Frame, Vertex, Binding, Bindable, SceneConst, FORMAT, DEPTH_FORMAT,
-  SAMPLES, cast,
+  Renderer, Mesh, Shader, Frame, View, Binding, Bindable, SceneConst, PipelineBuilder, cast,
 };
-use miau::scene::Transform;
+use miau::gfx::graph::{RenderGraph, Pass};
+use miau::gfx::reload::HotReload;
+use miau::scene::{Transform, ShadowPass, Bounds};
 use serde::{Serialize, Deserialize};
-// use game_shared::FurConst;
+use game_shared::FurConst;
 
 pub struct FurPass {
-  pipeline: wgpu::RenderPipeline,
-  furconst_layout: wgpu::BindGroupLayout,
+  pipeline: Rc<wgpu::RenderPipeline>,
 }
 
 impl FurPass {
   pub fn new(world: &World) -> Result<Self> {
-    let renderer = world.get_resource::<Renderer>().unwrap();
     let shader = world
       .get_resource::<Assets>()
       .unwrap()
       .load::<Shader>("game_shaders.spv")?;
-    let furconst_layout =
-      renderer
-        .device
-        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-          entries: &[wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-            ty: wgpu::BindingType::Buffer {
-              ty: wgpu::BufferBindingType::Uniform,
-              has_dynamic_offset: false,
-              min_binding_size: None,
-            },
-            count: None,
-          }],
-          label: None,
-        });
+    let pipeline = Self::pipeline(world, &shader);
+    HotReload::watch(
+      world,
+      "game_shaders.spv",
+      Self::pipeline,
+      |p: &mut Self, pipeline| p.pipeline = pipeline,
+    );
+    world.get_resource_mut::<RenderGraph>().unwrap().add(
+      Pass::new("fur", Self::pass)
+        .read("shadow")
+        .write("color")
+        .write("depth")
+        .after("standard"),
+    );
+    Ok(Self { pipeline })
+  }
 
-    let pipeline_layout = renderer
-      .device
-      .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        bind_group_layouts: &[&renderer.scene_layout, &furconst_layout],
-        push_constant_ranges: &[wgpu::PushConstantRange {
-          stages: wgpu::ShaderStages::VERTEX,
-          range: 0..mem::size_of::<Mat4>() as _,
-        }],
-        label: None,
-      });
-    let pipeline = renderer
-      .device
-      .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-          module: &shader.0,
-          entry_point: "main_v",
-          buffers: &[wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<Vertex>() as _,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2=> Float32x3],
-          }],
-        },
-        fragment: Some(wgpu::FragmentState {
-          module: &shader.0,
-          entry_point: "main_f",
-          targets: &[Some(wgpu::ColorTargetState {
-            format: FORMAT,
-            blend: Some(wgpu::BlendState::REPLACE),
-            write_mask: wgpu::ColorWrites::ALL,
-          })],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: Some(wgpu::DepthStencilState {
-          format: DEPTH_FORMAT,
-          depth_write_enabled: true,
-          depth_compare: wgpu::CompareFunction::Less,
-          stencil: wgpu::StencilState::default(),
-          bias: wgpu::DepthBiasState::default(),
-        }),
-        multisample: wgpu::MultisampleState {
-          count: SAMPLES,
-          mask: !0,
-          alpha_to_coverage_enabled: false,
-        },
-        multiview: None,
-        label: None,
-      });
-    world.add_system(stage::DRAW, Self::pass);
-    Ok(Self {
-      pipeline,
-      furconst_layout,
-    })
+  fn pipeline(world: &World, shader: &Shader) -> Rc<wgpu::RenderPipeline> {
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    PipelineBuilder::new(shader, "main_v", "main_f")
+      .layout(SceneConst::get_layout(world))
+      .layout(FurConst::get_layout(world))
+      .layout(&renderer.shadow_layout)
+      .push_constants(wgpu::ShaderStages::VERTEX, 0..mem::size_of::<Mat4>() as _)
+      .build(world)
   }
 
   fn pass(world: &World) -> Result {
     let renderer = world.get_resource_mut::<Renderer>().unwrap();
     let frame = world.get_resource_mut::<Frame>().unwrap();
     let pipeline = world.get_resource::<FurPass>().unwrap();
+    let view = world.get_resource::<View>().unwrap();
+    let graph = world.get_resource::<RenderGraph>().unwrap();
     let mut models = world.get_mut::<FurModel>();
     let mut render_pass = frame
       .encoder
       .begin_render_pass(&wgpu::RenderPassDescriptor {
-        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-          view: &renderer.textures.fb,
-          resolve_target: Some(&frame.surface_view),
-          ops: wgpu::Operations {
-            load: wgpu::LoadOp::Load,
-            store: wgpu::StoreOp::Store,
-          },
-        })],
-        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-          view: &renderer.textures.depth,
-          depth_ops: Some(wgpu::Operations {
-            load: wgpu::LoadOp::Load,
-            store: wgpu::StoreOp::Store,
-          }),
-          stencil_ops: None,
-        }),
+        color_attachments: &[Some(graph.color_attachment("color"))],
+        depth_stencil_attachment: Some(graph.depth_attachment("depth")),
         occlusion_query_set: None,
         timestamp_writes: None,
         label: None,
@@ -128,10 +71,22 @@ impl FurPass {
       .get_resource::<Binding<SceneConst>>()
       .unwrap()
       .bind(&mut render_pass, 0);
+    world
+      .get_resource::<ShadowPass>()
+      .unwrap()
+      .bind(&mut render_pass, 2);
 
     for (e, model) in &mut models {
-      if let Some(t) = e.get_one_mut::<Transform>() {
-        render_pass.set_push_constants(wgpu::ShaderStages::VERTEX, 0, cast(&t.as_mat4()));
+      if let Some(m) = Transform::model_matrix(world, e) {
+        let height = model.consts.data().height;
+        let bounds = Bounds {
+          aabb: model.mesh.aabb.inflate(height).transform(&m),
+          sphere: model.mesh.sphere.inflate(height).transform(&m),
+        };
+        if !view.frustum.intersects(&bounds) {
+          continue;
+        }
+        render_pass.set_push_constants(wgpu::ShaderStages::VERTEX, 0, cast(&m));
         model.consts.update(&renderer.queue);
         model.consts.bind(&mut render_pass, 1);
         model
@@ -151,7 +106,7 @@ pub struct FurModel {
 }
 
 impl FurModel {
-  pub fn new(world: &World, mesh: Handle<Mesh>) -> Self {
+  pub fn new(_world: &World, mesh: Handle<Mesh>) -> Self {
     Self {
       mesh,
       consts: Binding::new(FurConst {
@@ -163,39 +118,28 @@ impl FurModel {
     }
   }
 
+  // for game code tuning the fur, the demo scene keeps the defaults and uses the ui sliders
+  #[allow(dead_code)]
   pub fn layers(mut self, layers: u32) -> Self {
     self.consts.data_mut().layers = layers;
     self
   }
 
+  #[allow(dead_code)]
   pub fn density(mut self, density: f32) -> Self {
     self.consts.data_mut().density = density;
     self
   }
 
+  #[allow(dead_code)]
   pub fn height(mut self, height: f32) -> Self {
     self.consts.data_mut().height = height;
     self
   }
 
+  #[allow(dead_code)]
   pub fn thickness(mut self, thickness: f32) -> Self {
     self.consts.data_mut().thickness = thickness;
     self
   }
 }
-
-// dont duplicate
-#[repr(C)]
-#[derive(Serialize, Deserialize)]
-pub struct FurConst {
-  pub layers: u32,
-  pub density: f32,
-  pub height: f32,
-  pub thickness: f32,
-}
-
-impl Bindable for FurConst {
-  fn get_layout(world: &World) -> &wgpu::BindGroupLayout {
-    &world.get_resource::<FurPass>().unwrap().furconst_layout
-  }
-}
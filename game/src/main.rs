@@ -4,8 +4,12 @@ use std::fs::File;
 use log::LevelFilter;
 use miau::{Engine, Result};
 use miau::ecs::{World, Scene, stage};
-use miau::scene::{Transform, Model};
+use miau::scene::{
+  Transform, Interpolate, Model, DirectionalLight, PointLight, ShadowCaster, PostProcess, Camera,
+};
 use miau::assets::Assets;
+#[cfg(debug_assertions)]
+use miau::gfx::reload::HotReload;
 use miau::math::{Vec3, Quat};
 use miau::ui::imgui::Ui;
 use crate::fur::{FurPass, FurModel};
@@ -16,9 +20,13 @@ fn main() -> Result {
     .filter(Some("wgpu_core"), LevelFilter::Warn)
     .filter(Some("wgpu_hal"), LevelFilter::Warn)
     .init();
-  Engine::new()
+  let engine = Engine::new();
+  // shader and asset reloading is only for development builds
+  #[cfg(debug_assertions)]
+  let engine = engine.add_resource(HotReload::new());
+  engine
     .add_system(stage::START, start)
-    .add_system(stage::UPDATE, spin)
+    .add_system(stage::FIXED_UPDATE, spin)
     .add_system(stage::DRAW, ui)
     .run()
 }
@@ -29,7 +37,8 @@ fn start(world: &World) -> Result {
   world
     .spawn()
     .insert(Transform::new())
-    .insert(FurModel::new(world, assets.load("garfield.obj")?));
+    .insert(FurModel::new(world, assets.load("garfield.obj")?))
+    .insert(ShadowCaster(assets.load("garfield.obj")?));
 
   world
     .spawn()
@@ -40,10 +49,34 @@ fn start(world: &World) -> Result {
     )
     .insert(Model {
       mesh: assets.load("garfield.obj")?,
-      tex: assets.load("garfield.png")?,
+      material: assets.load("garfield.material")?,
     })
+    .insert(Interpolate::new())
     .insert(Spin);
 
+  world.spawn().insert(DirectionalLight {
+    direction: Vec3::new(-1.0, -2.0, -0.5),
+    color: Vec3::ONE,
+    intensity: 1.0,
+  });
+  world
+    .spawn()
+    .insert(Transform::new().pos(Vec3::new(-3.0, 2.0, 4.0)))
+    .insert(PointLight {
+      color: Vec3::new(1.0, 0.6, 0.2),
+      intensity: 12.0,
+      range: 8.0,
+    });
+  world
+    .spawn()
+    .insert(
+      Transform::new()
+        .pos(Vec3::splat(5.0))
+        .look_at(Vec3::ZERO, Vec3::Y),
+    )
+    .insert(Camera::new())
+    .insert(PostProcess::new().bloom(0.05, 1.0).fxaa(true));
+
   Scene::from_world(world).save(File::create("assets/test.scene")?)?;
   // assets.load::<Scene>("test.scene")?.into_world(world);
   Ok(())
@@ -1,6 +1,8 @@
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
-use syn::{DeriveInput, ExprPath, parse_macro_input};
+use syn::{Data, DeriveInput, ExprPath, Fields, Ident, LitStr, Token, Type, parse_macro_input};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
 use quote::{quote, format_ident};
 
 fn ctor() -> TokenStream2 {
@@ -44,9 +46,27 @@ pub fn component(_: TokenStream, input: TokenStream) -> TokenStream {
   .into()
 }
 
+struct AssetArgs {
+  loader: ExprPath,
+  extensions: Vec<LitStr>,
+}
+
+impl Parse for AssetArgs {
+  fn parse(input: ParseStream) -> syn::Result<Self> {
+    let loader = input.parse()?;
+    let mut extensions = vec![];
+    if input.parse::<Option<Token![,]>>()?.is_some() {
+      extensions = Punctuated::<LitStr, Token![,]>::parse_terminated(input)?
+        .into_iter()
+        .collect();
+    }
+    Ok(Self { loader, extensions })
+  }
+}
+
 #[proc_macro_attribute]
 pub fn asset(args: TokenStream, input: TokenStream) -> TokenStream {
-  let loader: ExprPath = parse_macro_input!(args);
+  let AssetArgs { loader, extensions } = parse_macro_input!(args);
   let input2 = TokenStream2::from(input.clone());
   let DeriveInput { ident, .. } = parse_macro_input!(input);
   let c = format_ident!("_{}_LOAD", ident);
@@ -62,7 +82,7 @@ pub fn asset(args: TokenStream, input: TokenStream) -> TokenStream {
       }
 
       extern fn i () {
-        unsafe { ::miau::assets::ASSET_LOADERS.insert(TypeId::of::<#ident>(), ::miau::assets::AssetLoader {loader, assets: vec![]}); }
+        unsafe { ::miau::assets::ASSET_LOADERS.insert(TypeId::of::<#ident>(), ::miau::assets::AssetLoader {loader, extensions: &[#(#extensions),*], assets: vec![]}); }
       }
       i
     };
@@ -70,3 +90,139 @@ pub fn asset(args: TokenStream, input: TokenStream) -> TokenStream {
   }
   .into()
 }
+
+#[proc_macro_derive(Std140)]
+pub fn std140_derive(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input);
+  std140_checks(&input)
+    .unwrap_or_else(|e| e.to_compile_error())
+    .into()
+}
+
+#[proc_macro_derive(Bindable, attributes(bindable))]
+pub fn bindable(input: TokenStream) -> TokenStream {
+  let input: DeriveInput = parse_macro_input!(input);
+  let checks = match std140_checks(&input) {
+    Ok(c) => c,
+    Err(e) => return e.to_compile_error().into(),
+  };
+  let mut stages = vec![];
+  for attr in input.attrs.iter().filter(|a| a.path().is_ident("bindable")) {
+    match attr.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated) {
+      Ok(idents) => stages.extend(idents),
+      Err(e) => return e.to_compile_error().into(),
+    }
+  }
+  let visibility = match stages.is_empty() {
+    true => quote!(::miau::wgpu::ShaderStages::VERTEX_FRAGMENT),
+    false => {
+      let stages = stages
+        .iter()
+        .map(|s| format_ident!("{}", s.to_string().to_uppercase()));
+      quote!(#(::miau::wgpu::ShaderStages::#stages)|*)
+    }
+  };
+  let ident = &input.ident;
+
+  quote! {
+    #checks
+
+    impl ::miau::gfx::Bindable for #ident {
+      fn get_layout(world: &::miau::ecs::World) -> &::miau::wgpu::BindGroupLayout {
+        static LAYOUT: ::miau::OnceLock<::miau::wgpu::BindGroupLayout> = ::miau::OnceLock::new();
+        LAYOUT.get_or_init(|| ::miau::gfx::uniform_layout(world, #visibility))
+      }
+    }
+  }
+  .into()
+}
+
+// const asserts that the repr(C) layout matches std140, only uses core so no_std crates can derive it
+fn std140_checks(input: &DeriveInput) -> syn::Result<TokenStream2> {
+  let DeriveInput {
+    ident, attrs, data, ..
+  } = input;
+  let repr_c = attrs
+    .iter()
+    .any(|a| a.path().is_ident("repr") && a.parse_args::<Ident>().map_or(false, |i| i == "C"));
+  if !repr_c {
+    return Err(syn::Error::new(
+      ident.span(),
+      "uniform structs need #[repr(C)]",
+    ));
+  }
+  let fields = match data {
+    Data::Struct(s) if matches!(s.fields, Fields::Unit) => {
+      return Err(syn::Error::new(
+        ident.span(),
+        "uniform structs need at least one field",
+      ))
+    }
+    Data::Struct(s) => &s.fields,
+    _ => {
+      return Err(syn::Error::new(
+        ident.span(),
+        "uniform layouts can only be derived for structs",
+      ))
+    }
+  };
+
+  let mut checks = vec![];
+  // tuple fields are named by their index
+  for (i, field) in fields.iter().enumerate() {
+    let ty = &field.ty;
+    let name = field
+      .ident
+      .as_ref()
+      .map_or(i.to_string(), |f| f.to_string());
+    let msg = format!("field `{}` of `{}` is not std140 aligned", name, ident);
+    let (align, inner) = std140(ty)?;
+    let inner = inner.map(|t| {
+      let msg = format!(
+        "`{}` needs a size that is a multiple of 16 in a uniform",
+        quote!(#t)
+      );
+      quote!(assert!(::core::mem::size_of::<#t>() % 16 == 0, #msg);)
+    });
+    checks.push(quote! {
+      let align = ::core::mem::align_of::<#ty>();
+      offset = (offset + align - 1) / align * align;
+      assert!(offset % #align == 0, #msg);
+      #inner
+      offset += ::core::mem::size_of::<#ty>();
+    });
+  }
+  let size_msg = format!("size of `{}` is not a multiple of 16", ident);
+
+  Ok(quote! {
+    const _: () = {
+      let mut offset = 0usize;
+      #(#checks)*
+      let _ = offset;
+      assert!(::core::mem::size_of::<#ident>() % 16 == 0, #size_msg);
+    };
+  })
+}
+
+// std140 alignment of a field and the type whose size has to be padded to 16
+fn std140(ty: &Type) -> syn::Result<(usize, Option<&Type>)> {
+  match ty {
+    Type::Array(a) => Ok((16, Some(&a.elem))),
+    Type::Path(p) => {
+      let name = p.path.segments.last().unwrap().ident.to_string();
+      match name.as_str() {
+        "f32" | "u32" | "i32" => Ok((4, None)),
+        "Vec2" | "UVec2" | "IVec2" => Ok((8, None)),
+        "Vec3" | "UVec3" | "IVec3" | "Vec4" | "UVec4" | "IVec4" | "Quat" | "Mat4" => Ok((16, None)),
+        "Mat2" | "Mat3" | "Mat3A" | "Vec3A" | "f64" | "bool" | "usize" | "isize" => Err(
+          syn::Error::new_spanned(ty, format!("`{}` has no matching std140 layout", name)),
+        ),
+        _ => Ok((16, Some(ty))),
+      }
+    }
+    _ => Err(syn::Error::new_spanned(
+      ty,
+      "unsupported type in a Bindable struct",
+    )),
+  }
+}
@@ -1,30 +1,166 @@
 #![no_std]
 use spirv_std::{spirv, Image, Sampler};
-use spirv_std::glam::{Vec4, Vec3, Vec2, Mat4};
-use miau_shared::SceneConst;
+use spirv_std::glam::{Vec4, Vec3, Vec2, Mat3, Mat4, Vec4Swizzles};
+use spirv_std::arch::{IndexUnchecked, ddx_vector, ddy_vector, fwidth};
+use core::f32::consts::PI;
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+use miau_shared::{
+  SceneConst, Lights, MaterialConst, ShadowConst, ShadowPush, SkyConst, PostConst,
+  LIGHT_DIRECTIONAL, LIGHT_SPOT, TONEMAP_ACES, TONEMAP_FILMIC,
+};
 
 #[spirv(vertex)]
+#[allow(clippy::too_many_arguments)]
 pub fn main_v(
   pos: Vec3,
   uv: Vec2,
-  _: Vec3,
-  #[spirv(push_constant)] transform: &Mat4,
+  normal: Vec3,
+  m0: Vec4,
+  m1: Vec4,
+  m2: Vec4,
+  m3: Vec4,
   #[spirv(uniform, descriptor_set = 0, binding = 0)] scene: &SceneConst,
   #[spirv(position)] out_pos: &mut Vec4,
   out_uv: &mut Vec2,
+  out_world: &mut Vec3,
+  out_normal: &mut Vec3,
 ) {
-  *out_pos = scene.cam * *transform * pos.extend(1.0);
+  let transform = Mat4::from_cols(m0, m1, m2, m3);
+  let world = transform * pos.extend(1.0);
+  *out_pos = scene.cam * world;
   *out_uv = uv;
+  *out_world = world.xyz();
+  // inverse transpose keeps normals perpendicular under non uniform scale
+  *out_normal = (Mat3::from_mat4(transform).inverse().transpose() * normal).normalize();
 }
 
 #[spirv(fragment)]
+#[allow(clippy::too_many_arguments)]
 pub fn main_f(
   uv: Vec2,
-  #[spirv(descriptor_set = 1, binding = 0)] tex: &Image!(2D, type=f32, sampled),
-  #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+  world: Vec3,
+  normal: Vec3,
+  #[spirv(uniform, descriptor_set = 0, binding = 0)] scene: &SceneConst,
+  #[spirv(uniform, descriptor_set = 1, binding = 0)] material: &MaterialConst,
+  #[spirv(descriptor_set = 1, binding = 1)] base_tex: &Image!(2D, type=f32, sampled),
+  #[spirv(descriptor_set = 1, binding = 2)] base_sampler: &Sampler,
+  #[spirv(descriptor_set = 1, binding = 3)] normal_tex: &Image!(2D, type=f32, sampled),
+  #[spirv(descriptor_set = 1, binding = 4)] normal_sampler: &Sampler,
+  #[spirv(descriptor_set = 1, binding = 5)] mr_tex: &Image!(2D, type=f32, sampled),
+  #[spirv(descriptor_set = 1, binding = 6)] mr_sampler: &Sampler,
+  #[spirv(descriptor_set = 1, binding = 7)] emissive_tex: &Image!(2D, type=f32, sampled),
+  #[spirv(descriptor_set = 1, binding = 8)] emissive_sampler: &Sampler,
+  #[spirv(uniform, descriptor_set = 2, binding = 0)] lights: &Lights,
+  #[spirv(uniform, descriptor_set = 3, binding = 0)] shadow: &ShadowConst,
+  #[spirv(descriptor_set = 3, binding = 1)] shadow_map: &Image!(2D, type=f32, sampled, depth, arrayed),
+  #[spirv(descriptor_set = 3, binding = 2)] shadow_sampler: &Sampler,
   out_color: &mut Vec4,
 ) {
-  *out_color = tex.sample(*sampler, uv);
+  let base_sample: Vec4 = base_tex.sample(*base_sampler, uv);
+  let base = material.base_color * base_sample;
+  let mut alpha = base.w;
+  if material.alpha_cutoff > 0.0 {
+    // sharpened around the cutoff so alpha to coverage only smooths the edge
+    alpha = ((alpha - material.alpha_cutoff) / fwidth(alpha).max(1e-4) + 0.5).clamp(0.0, 1.0);
+    if alpha <= 0.0 {
+      spirv_std::arch::kill();
+    }
+  }
+  let mr: Vec4 = mr_tex.sample(*mr_sampler, uv);
+  let emissive: Vec4 = emissive_tex.sample(*emissive_sampler, uv);
+  let normal_sample: Vec4 = normal_tex.sample(*normal_sampler, uv);
+  let tangent_normal = normal_sample.xyz() * 2.0 - 1.0;
+  let n = perturb_normal(normal.normalize(), world, uv, tangent_normal);
+  let surface = Surface {
+    pos: world,
+    normal: n,
+    view: (scene.eye.xyz() - world).normalize(),
+    albedo: base.xyz(),
+    metallic: material.metallic * mr.z,
+    roughness: (material.roughness * mr.y).clamp(0.04, 1.0),
+  };
+  let shadow = shadow.sample(shadow_map, shadow_sampler, world, scene.eye.xyz());
+  let color = shade(lights, &surface, shadow) + material.emissive.xyz() * emissive.xyz();
+  *out_color = color.extend(alpha);
+}
+
+struct Surface {
+  pos: Vec3,
+  normal: Vec3,
+  view: Vec3,
+  albedo: Vec3,
+  metallic: f32,
+  roughness: f32,
+}
+
+fn shade(lights: &Lights, s: &Surface, shadow: f32) -> Vec3 {
+  let f0 = Vec3::splat(0.04).lerp(s.albedo, s.metallic);
+  let a2 = s.roughness * s.roughness * s.roughness * s.roughness;
+  let k = (s.roughness + 1.0) * (s.roughness + 1.0) / 8.0;
+  let n_v = s.normal.dot(s.view).max(1e-4);
+  let mut color = lights.ambient * s.albedo;
+  let mut i = 0;
+  while i < lights.count {
+    let light = unsafe { lights.lights.index_unchecked(i as usize) };
+    let (dir, mut atten) = if light.pos.w == LIGHT_DIRECTIONAL {
+      (
+        -light.dir.xyz().normalize(),
+        if i == lights.shadowed { shadow } else { 1.0 },
+      )
+    } else {
+      let d = light.pos.xyz() - s.pos;
+      let dist = d.length();
+      let falloff = (1.0 - (dist / light.dir.w).powf(4.0)).clamp(0.0, 1.0);
+      (d / dist, falloff * falloff / (dist * dist + 1.0))
+    };
+    if light.pos.w == LIGHT_SPOT {
+      let cos = -dir.dot(light.dir.xyz().normalize());
+      atten *= smoothstep(light.cone.y, light.cone.x, cos);
+    }
+    let n_l = s.normal.dot(dir).max(0.0);
+    let half = (dir + s.view).normalize();
+    let n_h = s.normal.dot(half).max(0.0);
+    let d = n_h * n_h * (a2 - 1.0) + 1.0;
+    let ndf = a2 / (PI * d * d);
+    let g = n_l / (n_l * (1.0 - k) + k) * n_v / (n_v * (1.0 - k) + k);
+    let f = f0 + (1.0 - f0) * (1.0 - half.dot(s.view).max(0.0)).powf(5.0);
+    let specular = ndf * g * f / (4.0 * n_v * n_l + 1e-4);
+    let diffuse = (1.0 - f) * (1.0 - s.metallic) * s.albedo / PI;
+    color += (diffuse + specular) * light.color.xyz() * light.color.w * atten * n_l;
+    i += 1;
+  }
+  color
+}
+
+#[spirv(vertex)]
+pub fn shadow_v(
+  pos: Vec3,
+  _: Vec2,
+  _: Vec3,
+  #[spirv(push_constant)] transform: &ShadowPush,
+  #[spirv(position)] out_pos: &mut Vec4,
+) {
+  *out_pos = transform.light * transform.model * pos.extend(1.0);
+}
+
+fn perturb_normal(normal: Vec3, pos: Vec3, uv: Vec2, tangent_normal: Vec3) -> Vec3 {
+  let dp1 = ddx_vector(pos);
+  let dp2 = ddy_vector(pos);
+  let duv1 = ddx_vector(uv);
+  let duv2 = ddy_vector(uv);
+  let dp2perp = dp2.cross(normal);
+  let dp1perp = normal.cross(dp1);
+  let t = dp2perp * duv1.x + dp1perp * duv2.x;
+  let b = dp2perp * duv1.y + dp1perp * duv2.y;
+  let invmax = 1.0 / t.dot(t).max(b.dot(b)).max(1e-8).sqrt();
+  (t * invmax * tangent_normal.x + b * invmax * tangent_normal.y + normal * tangent_normal.z)
+    .normalize()
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+  let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+  t * t * (3.0 - 2.0 * t)
 }
 
 #[spirv(vertex)]
@@ -57,3 +193,190 @@ pub fn ui_f(
 ) {
   *out_color = tex.sample(*sampler, uv) * color.powf(2.2);
 }
+
+#[spirv(vertex)]
+pub fn sky_v(
+  #[spirv(vertex_index)] idx: i32,
+  #[spirv(uniform, descriptor_set = 0, binding = 0)] sky: &SkyConst,
+  #[spirv(position)] out_pos: &mut Vec4,
+  out_dir: &mut Vec3,
+) {
+  let ndc = Vec2::new(((idx << 1) & 2) as f32, (idx & 2) as f32) * 2.0 - 1.0;
+  *out_pos = Vec4::new(ndc.x, ndc.y, 1.0, 1.0);
+  *out_dir = (sky.inv_view_proj * *out_pos).xyz();
+}
+
+#[spirv(fragment)]
+pub fn sky_f(
+  dir: Vec3,
+  #[spirv(uniform, descriptor_set = 0, binding = 0)] sky: &SkyConst,
+  #[spirv(descriptor_set = 1, binding = 0)] tex: &Image!(cube, type=f32, sampled),
+  #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+  out_color: &mut Vec4,
+) {
+  let color: Vec4 = tex.sample(*sampler, dir.normalize());
+  *out_color = (color.xyz() * sky.params.x).extend(1.0);
+}
+
+#[spirv(vertex)]
+pub fn gizmo_v(
+  pos: Vec4,
+  color: Vec4,
+  #[spirv(uniform, descriptor_set = 0, binding = 0)] scene: &SceneConst,
+  #[spirv(position)] out_pos: &mut Vec4,
+  out_color: &mut Vec4,
+) {
+  *out_pos = scene.cam * pos;
+  *out_color = color;
+}
+
+#[spirv(fragment)]
+pub fn gizmo_f(color: Vec4, out_color: &mut Vec4) {
+  *out_color = color;
+}
+
+#[spirv(vertex)]
+pub fn post_v(
+  #[spirv(vertex_index)] idx: i32,
+  #[spirv(position)] out_pos: &mut Vec4,
+  out_uv: &mut Vec2,
+) {
+  let uv = Vec2::new(((idx << 1) & 2) as f32, (idx & 2) as f32);
+  *out_pos = Vec4::new(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+  *out_uv = uv;
+}
+
+// params: texel size of the source, threshold, 1 on the first downsample
+#[spirv(fragment)]
+pub fn bloom_down_f(
+  uv: Vec2,
+  #[spirv(push_constant)] params: &Vec4,
+  #[spirv(descriptor_set = 0, binding = 1)] tex: &Image!(2D, type=f32, sampled),
+  #[spirv(descriptor_set = 0, binding = 2)] sampler: &Sampler,
+  out_color: &mut Vec4,
+) {
+  let d = params.xy();
+  let a: Vec4 = tex.sample(*sampler, uv + Vec2::new(-d.x, -d.y));
+  let b: Vec4 = tex.sample(*sampler, uv + Vec2::new(d.x, -d.y));
+  let c: Vec4 = tex.sample(*sampler, uv + Vec2::new(-d.x, d.y));
+  let e: Vec4 = tex.sample(*sampler, uv + Vec2::new(d.x, d.y));
+  let mut color = (a + b + c + e).xyz() * 0.25;
+  if params.w > 0.0 {
+    let brightness = color.max_element();
+    color *= (brightness - params.z).max(0.0) / brightness.max(1e-4);
+  }
+  *out_color = color.extend(1.0);
+}
+
+#[spirv(fragment)]
+pub fn bloom_up_f(
+  uv: Vec2,
+  #[spirv(push_constant)] params: &Vec4,
+  #[spirv(descriptor_set = 0, binding = 1)] tex: &Image!(2D, type=f32, sampled),
+  #[spirv(descriptor_set = 0, binding = 2)] sampler: &Sampler,
+  out_color: &mut Vec4,
+) {
+  let mut sum = Vec3::ZERO;
+  let mut x: i32 = -1;
+  while x <= 1 {
+    let mut y: i32 = -1;
+    while y <= 1 {
+      let w = (2 - x.abs()) as f32 * (2 - y.abs()) as f32 / 16.0;
+      let s: Vec4 = tex.sample(*sampler, uv + Vec2::new(x as f32, y as f32) * params.xy());
+      sum += s.xyz() * w;
+      y += 1;
+    }
+    x += 1;
+  }
+  *out_color = sum.extend(1.0);
+}
+
+#[spirv(fragment)]
+pub fn post_f(
+  uv: Vec2,
+  #[spirv(uniform, descriptor_set = 0, binding = 0)] post: &PostConst,
+  #[spirv(descriptor_set = 0, binding = 1)] hdr: &Image!(2D, type=f32, sampled),
+  #[spirv(descriptor_set = 0, binding = 2)] sampler: &Sampler,
+  #[spirv(descriptor_set = 0, binding = 3)] bloom: &Image!(2D, type=f32, sampled),
+  #[spirv(descriptor_set = 0, binding = 4)] lut: &Image!(2D, type=f32, sampled),
+  out_color: &mut Vec4,
+) {
+  let hdr_sample: Vec4 = hdr.sample(*sampler, uv);
+  let mut color = hdr_sample.xyz();
+  if post.params.z > 0.0 {
+    let bloom_sample: Vec4 = bloom.sample(*sampler, uv);
+    color += bloom_sample.xyz() * post.params.z;
+  }
+  color *= post.params.x;
+  if post.params.y == TONEMAP_ACES {
+    color = (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14);
+  } else if post.params.y == TONEMAP_FILMIC {
+    color = hable(color * 2.0) / hable(Vec3::splat(11.2));
+  }
+  color = color.clamp(Vec3::ZERO, Vec3::ONE);
+  if post.lut.x > 0.0 {
+    color = grade(lut, sampler, color, post.lut.x);
+  }
+  if post.params.w > 0.0 {
+    let d = (uv - 0.5) * 2.0;
+    color *= 1.0 - post.params.w * smoothstep(0.5, 1.5, d.length());
+  }
+  *out_color = color.extend(1.0);
+}
+
+fn hable(x: Vec3) -> Vec3 {
+  let (a, b, c, d, e, f) = (0.15, 0.5, 0.1, 0.2, 0.02, 0.3);
+  (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+// lut is a horizontal strip of size x size slices, blue selects the slice
+fn grade(lut: &Image!(2D, type=f32, sampled), sampler: &Sampler, color: Vec3, size: f32) -> Vec3 {
+  let c = color.powf(1.0 / 2.2);
+  let z = c.z * (size - 1.0);
+  let slice = z.floor();
+  let x = (c.x * (size - 1.0) + 0.5) / (size * size);
+  let y = (c.y * (size - 1.0) + 0.5) / size;
+  let a: Vec4 = lut.sample_by_lod(*sampler, Vec2::new(x + slice / size, y), 0.0);
+  let b: Vec4 = lut.sample_by_lod(
+    *sampler,
+    Vec2::new(x + (slice + 1.0).min(size - 1.0) / size, y),
+    0.0,
+  );
+  a.xyz().lerp(b.xyz(), z - slice)
+}
+
+#[spirv(fragment)]
+pub fn fxaa_f(
+  uv: Vec2,
+  #[spirv(uniform, descriptor_set = 0, binding = 0)] post: &PostConst,
+  #[spirv(descriptor_set = 0, binding = 1)] tex: &Image!(2D, type=f32, sampled),
+  #[spirv(descriptor_set = 0, binding = 2)] sampler: &Sampler,
+  out_color: &mut Vec4,
+) {
+  let t = post.texel.xy();
+  let nw = luma(tex.sample_by_lod(*sampler, uv + Vec2::new(-t.x, -t.y), 0.0));
+  let ne = luma(tex.sample_by_lod(*sampler, uv + Vec2::new(t.x, -t.y), 0.0));
+  let sw = luma(tex.sample_by_lod(*sampler, uv + Vec2::new(-t.x, t.y), 0.0));
+  let se = luma(tex.sample_by_lod(*sampler, uv + Vec2::new(t.x, t.y), 0.0));
+  let m = luma(tex.sample_by_lod(*sampler, uv, 0.0));
+  let min = m.min(nw.min(ne).min(sw.min(se)));
+  let max = m.max(nw.max(ne).max(sw.max(se)));
+
+  let dir = Vec2::new(-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+  let reduce = ((nw + ne + sw + se) * 0.25 / 8.0).max(1.0 / 128.0);
+  let scale = 1.0 / (dir.x.abs().min(dir.y.abs()) + reduce);
+  let dir = (dir * scale).clamp(Vec2::splat(-8.0), Vec2::splat(8.0)) * t;
+
+  let a0: Vec4 = tex.sample_by_lod(*sampler, uv + dir * (1.0 / 3.0 - 0.5), 0.0);
+  let a1: Vec4 = tex.sample_by_lod(*sampler, uv + dir * (2.0 / 3.0 - 0.5), 0.0);
+  let b0: Vec4 = tex.sample_by_lod(*sampler, uv - dir * 0.5, 0.0);
+  let b1: Vec4 = tex.sample_by_lod(*sampler, uv + dir * 0.5, 0.0);
+  let a = (a0 + a1).xyz() * 0.5;
+  let b = a * 0.5 + (b0 + b1).xyz() * 0.25;
+  let l = luma(b.extend(1.0));
+  *out_color = if l < min || l > max { a } else { b }.extend(1.0);
+}
+
+fn luma(c: Vec4) -> f32 {
+  c.xyz().dot(Vec3::new(0.299, 0.587, 0.114)).sqrt()
+}
@@ -1,7 +1,16 @@
-#![no_std]
-use glam::{Vec3, Vec2, Mat4};
+#![cfg_attr(not(test), no_std)]
+use glam::{Vec3, Vec2, Vec4, Mat4};
+#[cfg(feature = "gpu")]
+use glam::Vec4Swizzles;
+#[cfg(feature = "gpu")]
+use spirv_std::{Image, Sampler};
+#[cfg(feature = "host")]
+use serde::{Serialize, Deserialize};
+#[cfg(feature = "host")]
+use miau_macros::Std140;
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct Vertex {
   pub pos: Vec3,
   pub uv: Vec2,
@@ -9,7 +18,156 @@ pub struct Vertex {
 }
 
 #[repr(C)]
+#[cfg_attr(feature = "host", derive(Serialize, Deserialize, Std140))]
 pub struct SceneConst {
   pub cam: Mat4,
   pub size: Vec2,
+  pub eye: Vec4,
+}
+
+pub const MAX_LIGHTS: usize = 32;
+
+pub const LIGHT_DIRECTIONAL: f32 = 0.0;
+pub const LIGHT_POINT: f32 = 1.0;
+pub const LIGHT_SPOT: f32 = 2.0;
+pub const NO_SHADOW: u32 = u32::MAX;
+
+// pos.w: kind, dir.w: range, color.w: intensity, cone: cos of inner/outer angle
+#[derive(Clone, Copy)]
+#[repr(C)]
+#[cfg_attr(feature = "host", derive(Serialize, Deserialize))]
+pub struct Light {
+  pub pos: Vec4,
+  pub dir: Vec4,
+  pub color: Vec4,
+  pub cone: Vec4,
+}
+
+// shadowed: index of the light the shadow map is rendered for, NO_SHADOW if none
+#[repr(C)]
+#[cfg_attr(feature = "host", derive(Serialize, Deserialize, Std140))]
+pub struct Lights {
+  pub ambient: Vec3,
+  pub count: u32,
+  pub shadowed: u32,
+  pub lights: [Light; MAX_LIGHTS],
+}
+
+#[repr(C)]
+#[cfg_attr(feature = "host", derive(Serialize, Deserialize, Std140))]
+pub struct MaterialConst {
+  pub base_color: Vec4,
+  pub emissive: Vec4,
+  pub metallic: f32,
+  pub roughness: f32,
+  pub alpha_cutoff: f32,
+  pub _pad: f32,
+}
+
+pub const CASCADES: usize = 4;
+
+// forward.w: 1 if shadows are enabled, params: depth bias and texel size
+#[repr(C)]
+#[cfg_attr(feature = "host", derive(Serialize, Deserialize, Std140))]
+pub struct ShadowConst {
+  pub cascades: [Mat4; CASCADES],
+  pub splits: Vec4,
+  pub forward: Vec4,
+  pub params: Vec4,
+}
+
+#[repr(C)]
+pub struct ShadowPush {
+  pub light: Mat4,
+  pub model: Mat4,
+}
+
+impl ShadowConst {
+  // uv, cascade and depth in the cascade's shadow map, w is negative outside the last cascade
+  pub fn project(&self, pos: Vec3, eye: Vec3) -> Vec4 {
+    let depth = (pos - eye).dot(self.forward.truncate());
+    if depth > self.splits.w {
+      return Vec4::new(0.0, 0.0, 0.0, -1.0);
+    }
+    let i = (depth > self.splits.x) as usize
+      + (depth > self.splits.y) as usize
+      + (depth > self.splits.z) as usize;
+    let cascade = match i {
+      0 => self.cascades[0],
+      1 => self.cascades[1],
+      2 => self.cascades[2],
+      _ => self.cascades[3],
+    };
+    let p = cascade * pos.extend(1.0);
+    Vec4::new(p.x * 0.5 + 0.5, 0.5 - p.y * 0.5, i as f32, p.z)
+  }
+
+  // 3x3 pcf, 1 outside the cascades or with shadows disabled
+  #[cfg(feature = "gpu")]
+  pub fn sample(
+    &self,
+    map: &Image!(2D, type=f32, sampled, depth, arrayed),
+    sampler: &Sampler,
+    pos: Vec3,
+    eye: Vec3,
+  ) -> f32 {
+    if self.forward.w == 0.0 {
+      return 1.0;
+    }
+    let p = self.project(pos, eye);
+    if p.w < 0.0 || p.w > 1.0 {
+      return 1.0;
+    }
+    let mut sum = 0.0;
+    let mut x = -1;
+    while x <= 1 {
+      let mut y = -1;
+      while y <= 1 {
+        let uv = p.xy() + Vec2::new(x as f32, y as f32) * self.params.y;
+        sum +=
+          map.sample_depth_reference_by_lod(*sampler, uv.extend(p.z), p.w - self.params.x, 0.0);
+        y += 1;
+      }
+      x += 1;
+    }
+    sum / 9.0
+  }
+}
+
+#[repr(C)]
+#[cfg_attr(feature = "host", derive(Serialize, Deserialize, Std140))]
+pub struct SkyConst {
+  pub inv_view_proj: Mat4,
+  pub params: Vec4,
+}
+
+pub const TONEMAP_NONE: f32 = 0.0;
+pub const TONEMAP_ACES: f32 = 1.0;
+pub const TONEMAP_FILMIC: f32 = 2.0;
+
+// params: exposure, tonemap, bloom intensity, vignette, lut.x: lut size or 0, texel: size of a pixel in uv
+#[repr(C)]
+#[cfg_attr(feature = "host", derive(Serialize, Deserialize, Std140))]
+pub struct PostConst {
+  pub params: Vec4,
+  pub lut: Vec4,
+  pub texel: Vec4,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use core::mem::size_of;
+
+  // sizes the shaders expect with std140 padding
+  #[test]
+  fn uniform_layouts() {
+    assert_eq!(size_of::<SceneConst>(), 96);
+    assert_eq!(size_of::<Light>(), 64);
+    assert_eq!(size_of::<Lights>(), 32 + 64 * MAX_LIGHTS);
+    assert_eq!(size_of::<MaterialConst>(), 48);
+    assert_eq!(size_of::<ShadowConst>(), 64 * CASCADES + 48);
+    assert_eq!(size_of::<SkyConst>(), 80);
+    assert_eq!(size_of::<PostConst>(), 48);
+  }
 }
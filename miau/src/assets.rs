@@ -3,9 +3,10 @@ use std::fs::File;
 use std::collections::HashMap;
 use std::any::{Any, TypeId};
 use std::rc::Rc;
+use std::cell::RefCell;
 use std::ops::Deref;
 use vach::archive::Archive;
-use serde::{Serialize, Deserialize, Deserializer, de::Error};
+use serde::{Serialize, Deserialize, Deserializer, de::Error, de::DeserializeOwned};
 use crate::{Result, world};
 use crate::ecs::World;
 
@@ -13,26 +14,111 @@ pub use miau_macros::asset;
 
 pub struct Assets {
   archive: Archive<File>,
+  loading: RefCell<Vec<String>>,
 }
 
 impl Assets {
   pub fn init(world: &World) -> Result {
     world.add_resource(Self {
       archive: Archive::new(File::open("assets.vach")?)?,
+      loading: RefCell::new(vec![]),
     });
     Ok(())
   }
 
   pub fn load<T: Any>(&self, path: &str) -> Result<Handle<T>> {
-    match unsafe { ASSET_LOADERS.get_mut(&TypeId::of::<T>()) } {
-      Some(loader) => match loader.assets.iter().find(|h| h.path == path) {
-        Some(asset) => Ok(asset.downcast()),
-        None => (loader.loader)(&self.load_raw(path)?).map(|a| {
-          loader.assets.push(Handle::new(path, a.clone()));
-          Handle::new(path, unsafe { a.downcast_unchecked() })
-        }),
-      },
-      None => Err(format!("no loader found for '{}'", std::any::type_name::<T>()).into()),
+    match unsafe { ASSET_LOADERS.contains_key(&TypeId::of::<T>()) } {
+      true => self.load_dyn(TypeId::of::<T>(), path).map(|a| a.downcast()),
+      false => Err(format!("no loader found for '{}'", std::any::type_name::<T>()).into()),
+    }
+  }
+
+  fn load_dyn(&self, t: TypeId, path: &str) -> Result<Handle<dyn Any>> {
+    if let Some(asset) = Self::cached(t, path) {
+      return Ok(asset);
+    }
+    if let Some((base, label)) = path.split_once('#') {
+      let ext = base.rsplit('.').next().unwrap_or_default();
+      let container = unsafe { ASSET_LOADERS.iter() }
+        .find(|(_, l)| l.extensions.contains(&ext))
+        .map(|(t, _)| *t)
+        .ok_or_else(|| format!("no loader found for '{}'", base))?;
+      self.load_dyn(container, base)?;
+      return Self::cached(t, path)
+        .ok_or_else(|| format!("'{}' has no asset '{}'", base, label).into());
+    }
+    let loader = unsafe { ASSET_LOADERS.get(&t).unwrap().loader };
+    let data = self.load_raw(path)?;
+    self.loading.borrow_mut().push(path.to_string());
+    let asset = loader(&data);
+    self.loading.borrow_mut().pop();
+    let asset = Handle::new(path, asset?);
+    unsafe { ASSET_LOADERS.get_mut(&t).unwrap() }
+      .assets
+      .push(asset.clone());
+    Ok(asset)
+  }
+
+  // loads an asset from data as if it was at path without caching it
+  pub(crate) fn load_from<T: Any>(&self, path: &str, data: &[u8]) -> Result<Handle<T>> {
+    let loader = unsafe { ASSET_LOADERS.get(&TypeId::of::<T>()) }
+      .ok_or_else(|| format!("no loader found for '{}'", std::any::type_name::<T>()))?
+      .loader;
+    self.loading.borrow_mut().push(path.to_string());
+    let asset = loader(data);
+    self.loading.borrow_mut().pop();
+    Ok(Handle::new(path, asset?).downcast())
+  }
+
+  // future loads of the asset's path return it, handles to the old one keep it alive
+  pub(crate) fn replace<T: Any>(&self, asset: &Handle<T>) {
+    let assets = &mut unsafe { ASSET_LOADERS.get_mut(&TypeId::of::<T>()).unwrap() }.assets;
+    let asset = Handle::new(&asset.path, asset.data.clone() as Rc<dyn Any>);
+    match assets.iter_mut().find(|h| h.path == asset.path) {
+      Some(h) => *h = asset,
+      None => assets.push(asset),
+    }
+  }
+
+  fn cached(t: TypeId, path: &str) -> Option<Handle<dyn Any>> {
+    unsafe { ASSET_LOADERS.get(&t) }?
+      .assets
+      .iter()
+      .find(|h| h.path == path)
+      .cloned()
+  }
+
+  pub fn label<T: Any>(&self, label: &str, asset: T) -> Result<Handle<T>> {
+    let path = format!("{}#{}", self.path().ok_or("not loading an asset")?, label);
+    let loader = unsafe { ASSET_LOADERS.get_mut(&TypeId::of::<T>()) }
+      .ok_or_else(|| format!("no loader found for '{}'", std::any::type_name::<T>()))?;
+    let asset = Rc::new(asset);
+    loader.assets.push(Handle::new(&path, asset.clone() as _));
+    Ok(Handle::new(&path, asset))
+  }
+
+  pub fn path(&self) -> Option<String> {
+    self.loading.borrow().last().cloned()
+  }
+
+  pub fn relative(&self, path: &str) -> String {
+    match self.path().as_deref().and_then(|p| p.rsplit_once('/')) {
+      Some((dir, _)) => format!("{}/{}", dir, path),
+      None => path.to_string(),
+    }
+  }
+
+  pub fn meta<T: DeserializeOwned + Default>(&self) -> Result<T> {
+    match self.path() {
+      Some(path) => self.meta_of(&path),
+      None => Ok(T::default()),
+    }
+  }
+
+  pub fn meta_of<T: DeserializeOwned + Default>(&self, path: &str) -> Result<T> {
+    match self.load_raw(&format!("{}.meta", path)).ok() {
+      Some(data) => Ok(serde_json::from_slice(&data)?),
+      None => Ok(T::default()),
     }
   }
 
@@ -66,6 +152,12 @@ impl<T: ?Sized> Handle<T> {
   }
 }
 
+impl<T: ?Sized> Clone for Handle<T> {
+  fn clone(&self) -> Self {
+    Self::new(&self.path, self.data.clone())
+  }
+}
+
 impl Handle<dyn Any> {
   fn downcast<T: Any>(&self) -> Handle<T> {
     Handle {
@@ -99,5 +191,61 @@ impl<'de, T: Any> Deserialize<'de> for Handle<T> {
 
 pub struct AssetLoader {
   pub loader: fn(&[u8]) -> Result<Rc<dyn Any>>,
+  pub extensions: &'static [&'static str],
   pub assets: Vec<Handle<dyn Any>>,
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+  use vach::builder::{Builder, BuilderConfig};
+
+  // a container labeling each of its words as a Word
+  #[asset(Words::load, "words")]
+  struct Words;
+
+  #[asset(Word::load)]
+  struct Word(String);
+
+  impl Words {
+    fn load(data: &[u8]) -> Result<Self> {
+      let assets = world().get_resource::<Assets>().unwrap();
+      for w in std::str::from_utf8(data)?.split_whitespace() {
+        assets.label(w, Word(w.to_string()))?;
+      }
+      Ok(Self)
+    }
+  }
+
+  impl Word {
+    fn load(data: &[u8]) -> Result<Self> {
+      Ok(Self(String::from_utf8(data.to_vec())?))
+    }
+  }
+
+  #[test]
+  fn labels() {
+    let path = std::env::temp_dir().join(format!("miau_assets_{}.vach", std::process::id()));
+    let mut builder = Builder::new();
+    builder
+      .add(Cursor::new(b"left right"), "assets/pair.words")
+      .unwrap();
+    builder
+      .dump(File::create(&path).unwrap(), &BuilderConfig::default())
+      .unwrap();
+    unsafe { crate::WORLD.write(World::new()) };
+    world().add_resource(Assets {
+      archive: Archive::new(File::open(&path).unwrap()).unwrap(),
+      loading: RefCell::new(vec![]),
+    });
+    let assets = world().get_resource::<Assets>().unwrap();
+
+    assert_eq!(assets.load::<Word>("pair.words#right").unwrap().0, "right");
+    assert_eq!(assets.load::<Word>("pair.words#left").unwrap().0, "left");
+    let missing = assets.load::<Word>("pair.words#up").err().unwrap();
+    assert_eq!(missing.to_string(), "'pair.words' has no asset 'up'");
+    assert!(assets.label("outside", Word(String::new())).is_err());
+    std::fs::remove_file(path).unwrap();
+  }
+}
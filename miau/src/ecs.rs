@@ -23,14 +23,18 @@ pub mod stage {
   pub const DRAW: u64 = 4;
   pub const POST_DRAW: u64 = 5;
   pub const EVENT: u64 = 6;
+  pub const PRE_UPDATE: u64 = 7;
+  pub const FIXED_UPDATE: u64 = 8;
 }
 
 pub trait System = Fn(&World) -> Result;
 
+type Systems = HashMap<u64, Vec<(&'static str, Box<dyn System>)>>;
+
 pub struct World {
   components: UnsafeCell<Scene>,
   resources: UnsafeCell<HashMap<TypeId, Box<dyn Any>>>,
-  systems: UnsafeCell<HashMap<u64, Vec<(&'static str, Box<dyn System>)>>>,
+  systems: UnsafeCell<Systems>,
 }
 
 impl World {
@@ -46,6 +50,8 @@ impl World {
     unsafe { &*self.components.get() }
   }
 
+  // systems share the world, callers make sure borrows of the scene don't overlap
+  #[allow(clippy::mut_from_ref)]
   pub(crate) fn components_mut(&self) -> &mut Scene {
     unsafe { &mut *self.components.get() }
   }
@@ -196,13 +202,14 @@ impl fmt::Debug for Entity<'_> {
 }
 
 #[doc(hidden)]
-pub static mut COMPONENTS: HashMap<
-  TypeId,
-  (
-    fn(Ref<dyn Any>) -> Ref<dyn erased_serde::Serialize>,
-    fn(&mut dyn ErasedDeserializer) -> Rc<RefCell<dyn Any>>,
-  ),
-> = HashMap::with_hasher(unsafe { mem::transmute([0u64; 2]) });
+pub type ComponentFns = (
+  fn(Ref<dyn Any>) -> Ref<dyn erased_serde::Serialize>,
+  fn(&mut dyn ErasedDeserializer) -> Rc<RefCell<dyn Any>>,
+);
+
+#[doc(hidden)]
+pub static mut COMPONENTS: HashMap<TypeId, ComponentFns> =
+  HashMap::with_hasher(unsafe { mem::transmute([0u64; 2]) });
 static mut CURRENT: TypeId = TypeId::of::<()>();
 
 #[derive(Clone)]
@@ -0,0 +1,305 @@
+use glam::{Vec3, Vec4, Mat4};
+use serde::{Serialize, Deserialize};
+use crate::Result;
+use crate::ecs::{World, component};
+use crate::gfx::Mesh;
+use crate::scene::{Transform, Model};
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Aabb {
+  pub min: Vec3,
+  pub max: Vec3,
+}
+
+impl Aabb {
+  pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+    let (min, max) = points
+      .into_iter()
+      .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), p| {
+        (min.min(p), max.max(p))
+      });
+    if min.x > max.x {
+      return Self {
+        min: Vec3::ZERO,
+        max: Vec3::ZERO,
+      };
+    }
+    Self { min, max }
+  }
+
+  pub fn center(&self) -> Vec3 {
+    (self.min + self.max) / 2.0
+  }
+
+  pub fn extents(&self) -> Vec3 {
+    (self.max - self.min) / 2.0
+  }
+
+  pub fn transform(&self, m: &Mat4) -> Self {
+    let center = m.transform_point3(self.center());
+    let e = self.extents();
+    let extents = m.x_axis.truncate().abs() * e.x
+      + m.y_axis.truncate().abs() * e.y
+      + m.z_axis.truncate().abs() * e.z;
+    Self {
+      min: center - extents,
+      max: center + extents,
+    }
+  }
+
+  pub fn inflate(&self, amount: f32) -> Self {
+    Self {
+      min: self.min - amount,
+      max: self.max + amount,
+    }
+  }
+
+  pub fn union(&self, other: &Self) -> Self {
+    Self {
+      min: self.min.min(other.min),
+      max: self.max.max(other.max),
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Sphere {
+  pub center: Vec3,
+  pub radius: f32,
+}
+
+impl Sphere {
+  pub fn from_points(points: &[Vec3]) -> Self {
+    let center = Aabb::from_points(points.iter().copied()).center();
+    Self {
+      center,
+      radius: points
+        .iter()
+        .map(|p| p.distance(center))
+        .fold(0.0, f32::max),
+    }
+  }
+
+  pub fn transform(&self, m: &Mat4) -> Self {
+    let scale = m
+      .x_axis
+      .truncate()
+      .length()
+      .max(m.y_axis.truncate().length())
+      .max(m.z_axis.truncate().length());
+    Self {
+      center: m.transform_point3(self.center),
+      radius: self.radius * scale,
+    }
+  }
+
+  pub fn inflate(&self, amount: f32) -> Self {
+    Self {
+      center: self.center,
+      radius: self.radius + amount,
+    }
+  }
+
+  pub fn union(&self, other: &Self) -> Self {
+    let d = other.center - self.center;
+    let dist = d.length();
+    if dist + other.radius <= self.radius {
+      return *self;
+    }
+    if dist + self.radius <= other.radius {
+      return *other;
+    }
+    let radius = (dist + self.radius + other.radius) / 2.0;
+    Self {
+      center: self.center + d * ((radius - self.radius) / dist),
+      radius,
+    }
+  }
+}
+
+#[component]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Bounds {
+  pub aabb: Aabb,
+  pub sphere: Sphere,
+}
+
+impl Bounds {
+  pub fn of(mesh: &Mesh, m: &Mat4) -> Self {
+    Self {
+      aabb: mesh.aabb.transform(m),
+      sphere: mesh.sphere.transform(m),
+    }
+  }
+
+  pub fn union(&self, other: &Self) -> Self {
+    Self {
+      aabb: self.aabb.union(&other.aabb),
+      sphere: self.sphere.union(&other.sphere),
+    }
+  }
+
+  pub(crate) fn update(world: &World) -> Result {
+    for (e, _) in world.get::<Transform>() {
+      let models = e.get::<Model>();
+      if models.is_empty() {
+        continue;
+      }
+      let Some(m) = Transform::model_matrix(world, &e) else {
+        continue;
+      };
+      let bounds = models
+        .iter()
+        .map(|model| Self::of(&model.mesh, &m))
+        .reduce(|a, b| a.union(&b))
+        .unwrap();
+      drop(models);
+      match e.get_one_mut::<Bounds>() {
+        Some(mut b) => *b = bounds,
+        None => {
+          e.insert(bounds);
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+// left, right, bottom, top, near, far
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+  pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+  pub fn from_matrix(m: Mat4) -> Self {
+    let (r0, r1, r2, r3) = (m.row(0), m.row(1), m.row(2), m.row(3));
+    Self {
+      planes: [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2]
+        .map(|p| p / p.truncate().length().max(1e-6)),
+    }
+  }
+
+  pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+    self
+      .planes
+      .iter()
+      .all(|p| p.truncate().dot(sphere.center) + p.w >= -sphere.radius)
+  }
+
+  pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+    self.planes.iter().all(|p| {
+      let n = p.truncate();
+      let v = Vec3::select(n.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+      n.dot(v) + p.w >= 0.0
+    })
+  }
+
+  pub fn intersects(&self, bounds: &Bounds) -> bool {
+    self.intersects_sphere(&bounds.sphere) && self.intersects_aabb(&bounds.aabb)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use glam::Quat;
+
+  fn unit() -> Aabb {
+    Aabb {
+      min: Vec3::splat(-1.0),
+      max: Vec3::splat(1.0),
+    }
+  }
+
+  fn sphere(x: f32, radius: f32) -> Sphere {
+    Sphere {
+      center: Vec3::new(x, 0.0, 0.0),
+      radius,
+    }
+  }
+
+  #[test]
+  fn aabb_from_points() {
+    let aabb = Aabb::from_points([Vec3::new(1.0, -2.0, 0.0), Vec3::new(-1.0, 3.0, 0.5)]);
+    assert_eq!(aabb.min, Vec3::new(-1.0, -2.0, 0.0));
+    assert_eq!(aabb.max, Vec3::new(1.0, 3.0, 0.5));
+    let empty = Aabb::from_points([]);
+    assert_eq!((empty.min, empty.max), (Vec3::ZERO, Vec3::ZERO));
+  }
+
+  #[test]
+  fn aabb_transform() {
+    let m = Mat4::from_scale_rotation_translation(
+      Vec3::new(2.0, 1.0, 1.0),
+      Quat::from_rotation_z(std::f32::consts::FRAC_PI_4),
+      Vec3::new(5.0, 0.0, 0.0),
+    );
+    let aabb = unit().transform(&m);
+    // every transformed corner is inside and the box is tight around them
+    let corners = (0..8).map(|i| {
+      m.transform_point3(Vec3::new(
+        if i & 1 != 0 { 1.0 } else { -1.0 },
+        if i & 2 != 0 { 1.0 } else { -1.0 },
+        if i & 4 != 0 { 1.0 } else { -1.0 },
+      ))
+    });
+    let tight = Aabb::from_points(corners);
+    assert!(aabb.min.abs_diff_eq(tight.min, 1e-5));
+    assert!(aabb.max.abs_diff_eq(tight.max, 1e-5));
+  }
+
+  #[test]
+  fn sphere_union() {
+    let big = sphere(0.0, 5.0);
+    let inside = sphere(1.0, 1.0);
+    assert_eq!(big.union(&inside), big);
+    assert_eq!(inside.union(&big), big);
+
+    let u = sphere(-2.0, 1.0).union(&sphere(3.0, 2.0));
+    assert!((u.radius - 4.0).abs() < 1e-5);
+    assert!(u.center.abs_diff_eq(Vec3::new(1.0, 0.0, 0.0), 1e-5));
+
+    let same = sphere(1.0, 2.0);
+    assert_eq!(same.union(&same), same);
+  }
+
+  #[test]
+  fn frustum_culling() {
+    let view = Mat4::look_at_lh(Vec3::ZERO, Vec3::Z, Vec3::Y);
+    let proj = Mat4::perspective_lh(1.0, 1.0, 0.1, 100.0);
+    let frustum = Frustum::from_matrix(proj * view);
+    let at = |p: Vec3| Bounds {
+      aabb: Aabb {
+        min: p - 0.5,
+        max: p + 0.5,
+      },
+      sphere: Sphere {
+        center: p,
+        radius: 0.87,
+      },
+    };
+    assert!(frustum.intersects(&at(Vec3::new(0.0, 0.0, 10.0))));
+    assert!(!frustum.intersects(&at(Vec3::new(0.0, 0.0, -10.0))));
+    assert!(!frustum.intersects(&at(Vec3::new(50.0, 0.0, 10.0))));
+    assert!(!frustum.intersects(&at(Vec3::new(0.0, 0.0, 200.0))));
+    // straddling the left plane
+    assert!(frustum.intersects(&at(Vec3::new(-5.4, 0.0, 10.0))));
+  }
+
+  #[test]
+  fn infinite_frustum() {
+    let view = Mat4::look_at_lh(Vec3::ZERO, Vec3::Z, Vec3::Y);
+    let proj = Mat4::perspective_infinite_lh(1.0, 1.0, 0.1);
+    let frustum = Frustum::from_matrix(proj * view);
+    assert!(frustum.planes.iter().all(|p| p.is_finite()));
+    assert!(frustum.intersects_sphere(&Sphere {
+      center: Vec3::new(0.0, 0.0, 1e6),
+      radius: 1.0,
+    }));
+    assert!(!frustum.intersects_sphere(&Sphere {
+      center: Vec3::new(0.0, 0.0, 0.01),
+      radius: 0.01,
+    }));
+  }
+}
@@ -0,0 +1,313 @@
+use std::mem;
+use std::rc::Rc;
+use std::f32::consts::TAU;
+use glam::{Vec3, Vec4, Mat4, BVec3};
+use crate::Result;
+use crate::ecs::World;
+use crate::assets::Assets;
+use crate::gfx::{
+  Bindable, Renderer, Shader, Frame, View, Binding, SceneConst, PipelineBuilder, cast_slice,
+};
+use crate::gfx::bounds::{Aabb, Sphere};
+use crate::gfx::graph::{RenderGraph, Pass};
+use crate::gfx::reload::HotReload;
+
+const SEGMENTS: usize = 32;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LineVertex {
+  pos: Vec4,
+  color: Vec4,
+}
+
+#[derive(Default)]
+struct Lines {
+  depth: Vec<LineVertex>,
+  overlay: Vec<LineVertex>,
+}
+
+// immediate mode debug lines, cleared after every frame, the ones drawn in FIXED_UPDATE
+// are kept until the next fixed step so they don't flicker on frames without one
+#[derive(Default)]
+pub struct Gizmos {
+  frame: Lines,
+  fixed: Lines,
+  in_fixed: bool,
+  on_top: bool,
+}
+
+impl Gizmos {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // shapes added after turning it off are drawn on top of everything
+  pub fn depth_test(&mut self, depth_test: bool) -> &mut Self {
+    self.on_top = !depth_test;
+    self
+  }
+
+  pub fn line(&mut self, a: Vec3, b: Vec3, color: Vec4) -> &mut Self {
+    let lines = match self.in_fixed {
+      true => &mut self.fixed,
+      false => &mut self.frame,
+    };
+    let lines = match self.on_top {
+      true => &mut lines.overlay,
+      false => &mut lines.depth,
+    };
+    lines.push(LineVertex {
+      pos: a.extend(1.0),
+      color,
+    });
+    lines.push(LineVertex {
+      pos: b.extend(1.0),
+      color,
+    });
+    self
+  }
+
+  pub fn aabb(&mut self, aabb: &Aabb, color: Vec4) -> &mut Self {
+    let corner = |i: usize| {
+      Vec3::select(
+        BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+        aabb.max,
+        aabb.min,
+      )
+    };
+    self.box_edges(corner, color)
+  }
+
+  // three circles around the axes
+  pub fn sphere(&mut self, sphere: &Sphere, color: Vec4) -> &mut Self {
+    let point = |axis: usize, i: usize| {
+      let (sin, cos) = (i as f32 / SEGMENTS as f32 * TAU).sin_cos();
+      let p = match axis {
+        0 => Vec3::new(0.0, cos, sin),
+        1 => Vec3::new(cos, 0.0, sin),
+        _ => Vec3::new(cos, sin, 0.0),
+      };
+      sphere.center + p * sphere.radius
+    };
+    for axis in 0..3 {
+      for i in 0..SEGMENTS {
+        self.line(point(axis, i), point(axis, i + 1), color);
+      }
+    }
+    self
+  }
+
+  // x red, y green, z blue
+  pub fn axes(&mut self, m: &Mat4, size: f32) -> &mut Self {
+    let origin = m.transform_point3(Vec3::ZERO);
+    for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+      self.line(origin, m.transform_point3(axis * size), axis.extend(1.0));
+    }
+    self
+  }
+
+  // on the xz plane with cells lines each way
+  pub fn grid(&mut self, center: Vec3, cells: u32, spacing: f32, color: Vec4) -> &mut Self {
+    let half = cells as f32 * spacing / 2.0;
+    for i in 0..=cells {
+      let d = i as f32 * spacing - half;
+      self.line(
+        center + Vec3::new(d, 0.0, -half),
+        center + Vec3::new(d, 0.0, half),
+        color,
+      );
+      self.line(
+        center + Vec3::new(-half, 0.0, d),
+        center + Vec3::new(half, 0.0, d),
+        color,
+      );
+    }
+    self
+  }
+
+  // between the near and far distances, View's own projection has no far plane
+  pub fn frustum(&mut self, view: &View, near: f32, far: f32, color: Vec4) -> &mut Self {
+    let inv = (Mat4::perspective_lh(view.fov, view.aspect, near, far) * view.view).inverse();
+    let corner = |i: usize| {
+      let x = if i & 1 != 0 { 1.0 } else { -1.0 };
+      let y = if i & 2 != 0 { 1.0 } else { -1.0 };
+      let z = if i & 4 != 0 { 1.0 } else { 0.0 };
+      inv.project_point3(Vec3::new(x, y, z))
+    };
+    self.box_edges(corner, color)
+  }
+
+  // corner i has its x, y and z at the max end for bits 1, 2 and 4
+  fn box_edges(&mut self, corner: impl Fn(usize) -> Vec3, color: Vec4) -> &mut Self {
+    for i in 0..8 {
+      for bit in [1, 2, 4] {
+        if i & bit == 0 {
+          self.line(corner(i), corner(i | bit), color);
+        }
+      }
+    }
+    self
+  }
+
+  pub(crate) fn clear(&mut self) {
+    self.frame.depth.clear();
+    self.frame.overlay.clear();
+    self.on_top = false;
+  }
+
+  // lines until end_fixed replace the ones from the previous fixed step
+  pub(crate) fn begin_fixed(&mut self) {
+    self.fixed.depth.clear();
+    self.fixed.overlay.clear();
+    self.in_fixed = true;
+    self.on_top = false;
+  }
+
+  pub(crate) fn end_fixed(&mut self) {
+    self.in_fixed = false;
+    self.on_top = false;
+  }
+}
+
+pub struct GizmoPass {
+  depth: Rc<wgpu::RenderPipeline>,
+  overlay: Rc<wgpu::RenderPipeline>,
+  vert_buf: wgpu::Buffer,
+}
+
+impl GizmoPass {
+  pub fn new(world: &World) -> Result<Self> {
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    let shader = world
+      .get_resource::<Assets>()
+      .unwrap()
+      .load::<Shader>("miau_shaders.spv")?;
+    let [depth, overlay] = Self::pipelines(world, &shader);
+    HotReload::watch(
+      world,
+      "miau_shaders.spv",
+      Self::pipelines,
+      |p: &mut Self, [depth, overlay]| {
+        p.depth = depth;
+        p.overlay = overlay;
+      },
+    );
+    let vert_buf = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+      size: 0,
+      usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+      label: None,
+    });
+    world.get_resource_mut::<RenderGraph>().unwrap().add(
+      Pass::new("gizmos", Self::pass)
+        .read("depth")
+        .write("color")
+        .after("transparent"),
+    );
+    Ok(Self {
+      depth,
+      overlay,
+      vert_buf,
+    })
+  }
+
+  // depth tested and on top
+  fn pipelines(world: &World, shader: &Shader) -> [Rc<wgpu::RenderPipeline>; 2] {
+    [
+      wgpu::CompareFunction::LessEqual,
+      wgpu::CompareFunction::Always,
+    ]
+    .map(|depth_compare| {
+      PipelineBuilder::new(shader, "gizmo_v", "gizmo_f")
+        .layout(SceneConst::get_layout(world))
+        .no_mesh()
+        .buffer(wgpu::VertexBufferLayout {
+          array_stride: mem::size_of::<LineVertex>() as _,
+          step_mode: wgpu::VertexStepMode::Vertex,
+          attributes: &wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4],
+        })
+        .topology(wgpu::PrimitiveTopology::LineList)
+        .blend(wgpu::BlendState::ALPHA_BLENDING)
+        .depth_write(false)
+        .depth_compare(depth_compare)
+        .build(world)
+    })
+  }
+
+  fn pass(world: &World) -> Result {
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    let frame = world.get_resource_mut::<Frame>().unwrap();
+    let pass = world.get_resource_mut::<GizmoPass>().unwrap();
+    let gizmos = world.get_resource::<Gizmos>().unwrap();
+    let graph = world.get_resource::<RenderGraph>().unwrap();
+    let (lines, fixed) = (&gizmos.frame, &gizmos.fixed);
+    let verts: Vec<_> = lines
+      .depth
+      .iter()
+      .chain(&fixed.depth)
+      .chain(&lines.overlay)
+      .chain(&fixed.overlay)
+      .copied()
+      .collect();
+    if verts.is_empty() {
+      return Ok(());
+    }
+
+    renderer.write_growing(&mut pass.vert_buf, cast_slice(&verts));
+
+    let mut render_pass = frame
+      .encoder
+      .begin_render_pass(&wgpu::RenderPassDescriptor {
+        color_attachments: &[Some(graph.color_attachment("color"))],
+        depth_stencil_attachment: Some(graph.depth_attachment("depth")),
+        occlusion_query_set: None,
+        timestamp_writes: None,
+        label: None,
+      });
+    world
+      .get_resource::<Binding<SceneConst>>()
+      .unwrap()
+      .bind(&mut render_pass, 0);
+    render_pass.set_vertex_buffer(0, pass.vert_buf.slice(..));
+    let depth = (lines.depth.len() + fixed.depth.len()) as u32;
+    for (pipeline, range) in [
+      (&pass.depth, 0..depth),
+      (&pass.overlay, depth..verts.len() as u32),
+    ] {
+      if !range.is_empty() {
+        render_pass.set_pipeline(pipeline);
+        render_pass.draw(range, 0..1);
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn frustum_is_finite() {
+    let view = View::new(Vec3::new(0.0, 2.0, -5.0), Vec3::ZERO, 1.2, 1.5, 0.1);
+    let mut gizmos = Gizmos::new();
+    gizmos.frustum(&view, 0.1, 20.0, Vec4::ONE);
+    assert_eq!(gizmos.frame.depth.len(), 24);
+    assert!(gizmos.frame.depth.iter().all(|v| v.pos.is_finite()));
+  }
+
+  #[test]
+  fn fixed_lines_outlive_frames() {
+    let mut gizmos = Gizmos::new();
+    gizmos.begin_fixed();
+    gizmos.line(Vec3::ZERO, Vec3::X, Vec4::ONE);
+    gizmos.end_fixed();
+    gizmos.line(Vec3::ZERO, Vec3::Y, Vec4::ONE);
+    gizmos.clear();
+    assert!(gizmos.frame.depth.is_empty());
+    assert_eq!(gizmos.fixed.depth.len(), 2);
+    gizmos.begin_fixed();
+    assert!(gizmos.fixed.depth.is_empty());
+  }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+use glam::{Vec2, Vec3, Vec4, Quat};
+use ::gltf::buffer::Source as BufferSource;
+use ::gltf::image::Source as ImageSource;
+use ::gltf::mesh::Mode;
+use ::gltf::texture::{MagFilter, MinFilter, WrappingMode};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use log::warn;
+use crate::{Result, world};
+use crate::assets::{asset, Assets, Handle};
+use crate::gfx::{Mesh, Texture, Vertex, TextureSettings, SamplerSettings, Filter, AddressMode};
+use crate::gfx::material::{Material, MaterialDesc, AlphaMode};
+use crate::gfx::standard::Model;
+use crate::scene::{Transform, Prefab, PrefabNode};
+
+#[asset(Gltf::load, "gltf", "glb")]
+pub struct Gltf {
+  pub meshes: Vec<Handle<Mesh>>,
+  pub materials: Vec<Handle<Material>>,
+  pub textures: Vec<Handle<Texture>>,
+  pub scenes: Vec<Handle<Prefab>>,
+  pub default_scene: Option<Handle<Prefab>>,
+}
+
+impl Gltf {
+  fn load(data: &[u8]) -> Result<Self> {
+    let assets = world().get_resource::<Assets>().unwrap();
+    let path = assets.path().unwrap_or_default();
+    let gltf = ::gltf::Gltf::from_slice(data)?;
+
+    let buffers = gltf
+      .buffers()
+      .map(|b| match b.source() {
+        BufferSource::Bin => gltf
+          .blob
+          .clone()
+          .ok_or_else(|| "missing binary chunk".into()),
+        BufferSource::Uri(uri) => read_uri(assets, uri),
+      })
+      .collect::<Result<Vec<_>>>()?;
+
+    let srgb: HashSet<usize> = gltf
+      .materials()
+      .flat_map(|m| {
+        [
+          m.pbr_metallic_roughness().base_color_texture(),
+          m.emissive_texture(),
+        ]
+      })
+      .flatten()
+      .map(|t| t.texture().index())
+      .collect();
+    let textures = gltf
+      .textures()
+      .map(|t| {
+        let data = match t.source().source() {
+          ImageSource::View { view, .. } => {
+            buffers[view.buffer().index()][view.offset()..view.offset() + view.length()].to_vec()
+          }
+          ImageSource::Uri { uri, .. } => read_uri(assets, uri)?,
+        };
+        let sampler = t.sampler();
+        let settings = TextureSettings {
+          srgb: srgb.contains(&t.index()),
+          mipmaps: true,
+          sampler: SamplerSettings {
+            mag_filter: match sampler.mag_filter() {
+              Some(MagFilter::Nearest) => Filter::Nearest,
+              _ => Filter::Linear,
+            },
+            min_filter: match sampler.min_filter() {
+              Some(
+                MinFilter::Nearest
+                | MinFilter::NearestMipmapNearest
+                | MinFilter::NearestMipmapLinear,
+              ) => Filter::Nearest,
+              _ => Filter::Linear,
+            },
+            mipmap_filter: match sampler.min_filter() {
+              Some(MinFilter::NearestMipmapNearest | MinFilter::LinearMipmapNearest) => {
+                Filter::Nearest
+              }
+              _ => Filter::Linear,
+            },
+            address_mode_u: address_mode(sampler.wrap_s()),
+            address_mode_v: address_mode(sampler.wrap_t()),
+            ..Default::default()
+          },
+        };
+        assets.label(
+          &format!("Texture{}", t.index()),
+          Texture::from_image(&image::load_from_memory(&data)?, &settings),
+        )
+      })
+      .collect::<Result<Vec<_>>>()?;
+
+    let tex = |t: Option<::gltf::texture::Info>| t.map(|t| textures[t.texture().index()].clone());
+    let materials = gltf
+      .materials()
+      .enumerate()
+      .map(|(i, m)| {
+        let pbr = m.pbr_metallic_roughness();
+        assets.label(
+          &format!("Material{}", i),
+          Material::new(MaterialDesc {
+            base_color: Vec4::from_array(pbr.base_color_factor()),
+            base_color_texture: tex(pbr.base_color_texture()),
+            normal_texture: m
+              .normal_texture()
+              .map(|t| textures[t.texture().index()].clone()),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            metallic_roughness_texture: tex(pbr.metallic_roughness_texture()),
+            emissive: Vec3::from_array(m.emissive_factor()),
+            emissive_texture: tex(m.emissive_texture()),
+            alpha_mode: match m.alpha_mode() {
+              ::gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+              ::gltf::material::AlphaMode::Mask => AlphaMode::Mask(m.alpha_cutoff().unwrap_or(0.5)),
+              ::gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+            },
+          }),
+        )
+      })
+      .collect::<Result<Vec<_>>>()?;
+    let default_material = gltf
+      .meshes()
+      .flat_map(|m| m.primitives())
+      .any(|p| p.material().index().is_none())
+      .then(|| assets.label("DefaultMaterial", Material::new(MaterialDesc::default())))
+      .transpose()?;
+
+    let mut meshes = vec![];
+    let mut models = vec![];
+    for mesh in gltf.meshes() {
+      let (mut verts, mut indices) = (vec![], vec![]);
+      let mut mesh_models = vec![];
+      for p in mesh.primitives() {
+        if p.mode() != Mode::Triangles {
+          warn!("Skipping {:?} primitive in '{}'.", p.mode(), path);
+          continue;
+        }
+        let reader = p.reader(|b| Some(&buffers[b.index()]));
+        let pos: Vec<Vec3> = reader
+          .read_positions()
+          .ok_or("primitive has no positions")?
+          .map(Vec3::from_array)
+          .collect();
+        let uvs: Vec<Vec2> = reader
+          .read_tex_coords(0)
+          .map_or(vec![], |t| t.into_f32().map(Vec2::from_array).collect());
+        let normals: Vec<Vec3> = reader
+          .read_normals()
+          .map_or(vec![], |n| n.map(Vec3::from_array).collect());
+        let prim_verts: Vec<Vertex> = pos
+          .iter()
+          .enumerate()
+          .map(|(i, pos)| Vertex {
+            pos: *pos,
+            uv: uvs.get(i).copied().unwrap_or_default(),
+            normal: normals.get(i).copied().unwrap_or(Vec3::Y),
+          })
+          .collect();
+        let prim_indices: Vec<u32> = reader
+          .read_indices()
+          .map_or((0..pos.len() as u32).collect(), |i| i.into_u32().collect());
+
+        let offset = verts.len() as u32;
+        indices.extend(prim_indices.iter().map(|i| i + offset));
+        verts.extend_from_slice(&prim_verts);
+        mesh_models.push(Model {
+          mesh: assets.label(
+            &format!("Mesh{}/Primitive{}", mesh.index(), p.index()),
+            Mesh::new(&prim_verts, &prim_indices),
+          )?,
+          material: p.material().index().map_or_else(
+            || default_material.clone().unwrap(),
+            |i| materials[i].clone(),
+          ),
+        });
+      }
+      meshes.push(assets.label(
+        &format!("Mesh{}", mesh.index()),
+        Mesh::new(&verts, &indices),
+      )?);
+      models.push(mesh_models);
+    }
+
+    let nodes = || {
+      gltf
+        .nodes()
+        .map(|n| {
+          let (t, r, s) = n.transform().decomposed();
+          PrefabNode {
+            name: n.name().map(String::from),
+            transform: Transform {
+              position: Vec3::from_array(t),
+              rotation: Quat::from_array(r),
+              scale: Vec3::from_array(s),
+            },
+            models: n.mesh().map_or(vec![], |m| models[m.index()].clone()),
+            children: n.children().map(|c| c.index()).collect(),
+          }
+        })
+        .collect()
+    };
+    let scenes = gltf
+      .scenes()
+      .map(|s| {
+        assets.label(
+          &format!("Scene{}", s.index()),
+          Prefab {
+            nodes: nodes(),
+            roots: s.nodes().map(|n| n.index()).collect(),
+          },
+        )
+      })
+      .collect::<Result<Vec<_>>>()?;
+
+    Ok(Self {
+      meshes,
+      materials,
+      textures,
+      default_scene: gltf.default_scene().map(|s| scenes[s.index()].clone()),
+      scenes,
+    })
+  }
+}
+
+fn read_uri(assets: &Assets, uri: &str) -> Result<Vec<u8>> {
+  match uri.strip_prefix("data:") {
+    Some(data) => {
+      Ok(STANDARD.decode(data.split_once(";base64,").ok_or("unsupported data uri")?.1)?)
+    }
+    None => assets.load_raw(&assets.relative(uri)),
+  }
+}
+
+fn address_mode(mode: WrappingMode) -> AddressMode {
+  match mode {
+    WrappingMode::ClampToEdge => AddressMode::Clamp,
+    WrappingMode::MirroredRepeat => AddressMode::Mirror,
+    WrappingMode::Repeat => AddressMode::Repeat,
+  }
+}
@@ -0,0 +1,490 @@
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet, BTreeSet};
+use winit::dpi::PhysicalSize;
+use log::warn;
+use crate::Result;
+use crate::ecs::{World, System};
+use crate::gfx::Renderer;
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextureDesc {
+  pub format: wgpu::TextureFormat,
+  pub samples: u32,
+  pub mips: u32,
+  pub scale: u32,
+  pub resolve: bool,
+}
+
+impl TextureDesc {
+  pub fn new(format: wgpu::TextureFormat) -> Self {
+    Self {
+      format,
+      samples: 1,
+      mips: 1,
+      scale: 1,
+      resolve: false,
+    }
+  }
+
+  pub fn samples(mut self, samples: u32) -> Self {
+    self.samples = samples;
+    self
+  }
+
+  pub fn mips(mut self, mips: u32) -> Self {
+    self.mips = mips;
+    self
+  }
+
+  // size is the surface size divided by scale
+  pub fn scale(mut self, scale: u32) -> Self {
+    self.scale = scale;
+    self
+  }
+
+  // multisampled textures get resolved into a single sampled one which is what view() returns
+  pub fn resolve(mut self) -> Self {
+    self.resolve = true;
+    self
+  }
+}
+
+pub struct Pass {
+  name: &'static str,
+  reads: Vec<&'static str>,
+  writes: Vec<&'static str>,
+  after: Vec<&'static str>,
+  before: Vec<&'static str>,
+  run: Box<dyn System>,
+}
+
+impl Pass {
+  pub fn new<S: System + 'static>(name: &'static str, run: S) -> Self {
+    Self {
+      name,
+      reads: vec![],
+      writes: vec![],
+      after: vec![],
+      before: vec![],
+      run: Box::new(run),
+    }
+  }
+
+  pub fn read(mut self, attachment: &'static str) -> Self {
+    self.reads.push(attachment);
+    self
+  }
+
+  // passes writing the same attachment need an order between them, through after(),
+  // before() or a read in between, the first one in that order clears it
+  pub fn write(mut self, attachment: &'static str) -> Self {
+    self.writes.push(attachment);
+    self
+  }
+
+  pub fn after(mut self, pass: &'static str) -> Self {
+    self.after.push(pass);
+    self
+  }
+
+  pub fn before(mut self, pass: &'static str) -> Self {
+    self.before.push(pass);
+    self
+  }
+}
+
+struct Transient {
+  desc: TextureDesc,
+  msaa: Option<wgpu::TextureView>,
+  views: Vec<wgpu::TextureView>,
+}
+
+pub struct RenderGraph {
+  passes: Vec<Pass>,
+  order: Vec<usize>,
+  first_writers: HashMap<&'static str, usize>,
+  dirty: bool,
+  textures: HashMap<&'static str, Transient>,
+  externals: HashSet<&'static str>,
+  size: PhysicalSize<u32>,
+  generation: u64,
+  current: Cell<usize>,
+}
+
+impl RenderGraph {
+  pub fn new(size: PhysicalSize<u32>) -> Self {
+    Self {
+      passes: vec![],
+      order: vec![],
+      first_writers: HashMap::new(),
+      dirty: false,
+      textures: HashMap::new(),
+      externals: HashSet::new(),
+      size,
+      generation: 0,
+      current: Cell::new(0),
+    }
+  }
+
+  // replaces a pass with the same name
+  pub fn add(&mut self, pass: Pass) {
+    match self.passes.iter_mut().find(|p| p.name == pass.name) {
+      Some(p) => *p = pass,
+      None => self.passes.push(pass),
+    }
+    self.dirty = true;
+  }
+
+  pub fn remove(&mut self, name: &str) {
+    self.passes.retain(|p| p.name != name);
+    self.dirty = true;
+  }
+
+  pub fn passes(&self) -> Vec<&'static str> {
+    self.order.iter().map(|i| self.passes[*i].name).collect()
+  }
+
+  pub fn texture(&mut self, name: &'static str, desc: TextureDesc) {
+    self.textures.insert(
+      name,
+      Transient {
+        desc,
+        msaa: None,
+        views: vec![],
+      },
+    );
+  }
+
+  // attachments owned outside the graph like the surface, passes can read and write them
+  // for ordering but the graph doesn't allocate them
+  pub fn external(&mut self, name: &'static str) {
+    self.externals.insert(name);
+  }
+
+  pub fn view(&self, name: &str) -> &wgpu::TextureView {
+    &self.transient(name).views[0]
+  }
+
+  pub fn mip(&self, name: &str, level: usize) -> &wgpu::TextureView {
+    &self.transient(name).views[level + 1]
+  }
+
+  pub fn mips(&self, name: &str) -> usize {
+    self.transient(name).views.len() - 1
+  }
+
+  // changes whenever transient textures are recreated, views from before are stale
+  pub fn generation(&self) -> u64 {
+    self.generation
+  }
+
+  pub fn load<T>(&self, name: &str, clear: T) -> wgpu::LoadOp<T> {
+    match self.first_writers.get(name) {
+      Some(i) if *i == self.current.get() => wgpu::LoadOp::Clear(clear),
+      _ => wgpu::LoadOp::Load,
+    }
+  }
+
+  pub fn color_attachment(&self, name: &str) -> wgpu::RenderPassColorAttachment {
+    let t = self.transient(name);
+    let (view, resolve_target) = match &t.msaa {
+      Some(msaa) => (msaa, Some(&t.views[0])),
+      None => (&t.views[0], None),
+    };
+    wgpu::RenderPassColorAttachment {
+      view,
+      resolve_target,
+      ops: wgpu::Operations {
+        load: self.load(name, wgpu::Color::BLACK),
+        store: wgpu::StoreOp::Store,
+      },
+    }
+  }
+
+  pub fn depth_attachment(&self, name: &str) -> wgpu::RenderPassDepthStencilAttachment {
+    wgpu::RenderPassDepthStencilAttachment {
+      view: self.view(name),
+      depth_ops: Some(wgpu::Operations {
+        load: self.load(name, 1.0),
+        store: wgpu::StoreOp::Store,
+      }),
+      stencil_ops: None,
+    }
+  }
+
+  pub(crate) fn resize(&mut self, size: PhysicalSize<u32>) {
+    self.size = size;
+    for t in self.textures.values_mut() {
+      t.msaa = None;
+      t.views.clear();
+    }
+  }
+
+  pub(crate) fn execute(world: &World) -> Result {
+    let graph = world.get_resource_mut::<RenderGraph>().unwrap();
+    if graph.dirty {
+      graph.sort()?;
+      graph.dirty = false;
+    }
+    graph.allocate(&world.get_resource::<Renderer>().unwrap().device);
+    for i in 0..graph.order.len() {
+      let pass = &graph.passes[graph.order[i]];
+      graph.current.set(graph.order[i]);
+      (pass.run)(world).map_err(|e| format!("Error in render pass '{}': {}", pass.name, e))?;
+    }
+    Ok(())
+  }
+
+  // attachments a pass declares are checked in sort(), this only trips on views it never declared
+  fn transient(&self, name: &str) -> &Transient {
+    match self.textures.get(name) {
+      Some(t) if !t.views.is_empty() => t,
+      _ => panic!("No transient texture '{}' in the render graph.", name),
+    }
+  }
+
+  // writers of an attachment run before its readers, writers of the same attachment must be
+  // ordered against each other, remaining ties are broken by insertion order
+  fn sort(&mut self) -> Result {
+    for p in &self.passes {
+      for a in p.reads.iter().chain(&p.writes) {
+        if !self.textures.contains_key(a) && !self.externals.contains(a) {
+          return Err(
+            format!(
+              "Render pass '{}' uses undeclared attachment '{}'.",
+              p.name, a
+            )
+            .into(),
+          );
+        }
+      }
+    }
+
+    let index: HashMap<_, _> = self
+      .passes
+      .iter()
+      .enumerate()
+      .map(|(i, p)| (p.name, i))
+      .collect();
+    let mut edges = vec![vec![]; self.passes.len()];
+    for (i, p) in self.passes.iter().enumerate() {
+      let deps = p.after.iter().map(|n| (n, true));
+      for (n, after) in deps.chain(p.before.iter().map(|n| (n, false))) {
+        match index.get(n) {
+          Some(j) if after => edges[*j].push(i),
+          Some(j) => edges[i].push(*j),
+          None => warn!("Render pass '{}' depends on missing pass '{}'.", p.name, n),
+        }
+      }
+      for r in p.reads.iter().filter(|r| !p.writes.contains(r)) {
+        for (j, w) in self.passes.iter().enumerate() {
+          if w.writes.contains(r) {
+            edges[j].push(i);
+          }
+        }
+      }
+    }
+
+    let mut indegree = vec![0; self.passes.len()];
+    for b in edges.iter().flatten() {
+      indegree[*b] += 1;
+    }
+    let mut ready: BTreeSet<_> = (0..self.passes.len())
+      .filter(|i| indegree[*i] == 0)
+      .collect();
+    self.order.clear();
+    while let Some(i) = ready.pop_first() {
+      self.order.push(i);
+      for b in &edges[i] {
+        indegree[*b] -= 1;
+        if indegree[*b] == 0 {
+          ready.insert(*b);
+        }
+      }
+    }
+    if self.order.len() < self.passes.len() {
+      let cycle: Vec<_> = (0..self.passes.len())
+        .filter(|i| indegree[*i] > 0)
+        .map(|i| self.passes[i].name)
+        .collect();
+      return Err(format!("Render graph has a cycle between {:?}.", cycle).into());
+    }
+
+    let reaches = |from: usize, to: usize| {
+      let mut stack = vec![from];
+      let mut seen = vec![false; edges.len()];
+      while let Some(i) = stack.pop() {
+        if i == to {
+          return true;
+        }
+        if !std::mem::replace(&mut seen[i], true) {
+          stack.extend(&edges[i]);
+        }
+      }
+      false
+    };
+    let attachments: BTreeSet<_> = self.passes.iter().flat_map(|p| &p.writes).collect();
+    for a in attachments {
+      let writers: Vec<_> = self
+        .order
+        .iter()
+        .filter(|i| self.passes[**i].writes.contains(a))
+        .collect();
+      for w in writers.windows(2) {
+        if !reaches(*w[0], *w[1]) {
+          return Err(
+            format!(
+              "Render passes '{}' and '{}' both write '{}' without an order between them.",
+              self.passes[*w[0]].name, self.passes[*w[1]].name, a
+            )
+            .into(),
+          );
+        }
+      }
+    }
+
+    self.first_writers.clear();
+    for i in &self.order {
+      for w in &self.passes[*i].writes {
+        self.first_writers.entry(w).or_insert(*i);
+      }
+    }
+    Ok(())
+  }
+
+  fn allocate(&mut self, device: &wgpu::Device) {
+    for t in self.textures.values_mut().filter(|t| t.views.is_empty()) {
+      self.generation += 1;
+      let (width, height) = (
+        (self.size.width / t.desc.scale).max(1),
+        (self.size.height / t.desc.scale).max(1),
+      );
+      let mips = t.desc.mips.min(32 - width.min(height).leading_zeros());
+      let desc = wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+          width,
+          height,
+          depth_or_array_layers: 1,
+        },
+        mip_level_count: mips,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: t.desc.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+        label: None,
+      };
+      let msaa = wgpu::TextureDescriptor {
+        mip_level_count: 1,
+        sample_count: t.desc.samples,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        ..desc
+      };
+      let texture = if t.desc.samples > 1 && !t.desc.resolve {
+        device.create_texture(&msaa)
+      } else {
+        device.create_texture(&desc)
+      };
+      t.msaa = (t.desc.samples > 1 && t.desc.resolve).then(|| {
+        device
+          .create_texture(&msaa)
+          .create_view(&wgpu::TextureViewDescriptor::default())
+      });
+      t.views = vec![texture.create_view(&wgpu::TextureViewDescriptor::default())];
+      t.views.extend((0..texture.mip_level_count()).map(|i| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+          base_mip_level: i,
+          mip_level_count: Some(1),
+          ..Default::default()
+        })
+      }));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn pass(name: &'static str) -> Pass {
+    Pass::new(name, |_: &World| Ok(()))
+  }
+
+  fn graph(passes: Vec<Pass>) -> RenderGraph {
+    let mut graph = RenderGraph::new(PhysicalSize::new(1, 1));
+    graph.texture("color", TextureDesc::new(wgpu::TextureFormat::Rgba16Float));
+    graph.texture("depth", TextureDesc::new(wgpu::TextureFormat::Depth32Float));
+    graph.external("surface");
+    for p in passes {
+      graph.add(p);
+    }
+    graph
+  }
+
+  fn clears(graph: &RenderGraph, pass: &str, attachment: &str) -> bool {
+    let i = graph.passes.iter().position(|p| p.name == pass).unwrap();
+    graph.current.set(i);
+    matches!(graph.load(attachment, ()), wgpu::LoadOp::Clear(()))
+  }
+
+  #[test]
+  fn writers_before_readers() {
+    let mut graph = graph(vec![
+      pass("post").read("color").write("surface"),
+      pass("sky").read("depth").write("color").after("opaque"),
+      pass("opaque").write("color").write("depth"),
+    ]);
+    graph.sort().unwrap();
+    assert_eq!(graph.passes(), ["opaque", "sky", "post"]);
+  }
+
+  #[test]
+  fn after_and_before() {
+    let mut graph = graph(vec![
+      pass("b").write("color"),
+      pass("c").write("color").after("b"),
+      pass("a").write("color").before("b"),
+    ]);
+    graph.sort().unwrap();
+    assert_eq!(graph.passes(), ["a", "b", "c"]);
+  }
+
+  #[test]
+  fn cycle() {
+    let mut graph = graph(vec![
+      pass("a").write("color").after("b"),
+      pass("b").write("depth").after("a"),
+    ]);
+    assert!(graph.sort().unwrap_err().to_string().contains("cycle"));
+  }
+
+  #[test]
+  fn unordered_writers() {
+    let mut graph = graph(vec![pass("a").write("color"), pass("b").write("color")]);
+    assert!(graph
+      .sort()
+      .unwrap_err()
+      .to_string()
+      .contains("without an order"));
+  }
+
+  #[test]
+  fn undeclared_attachment() {
+    let mut graph = graph(vec![pass("a").read("colour").write("surface")]);
+    assert!(graph.sort().unwrap_err().to_string().contains("'colour'"));
+  }
+
+  #[test]
+  fn first_writer_clears() {
+    let mut graph = graph(vec![
+      pass("opaque").write("color").write("depth"),
+      pass("sky").read("depth").write("color"),
+      pass("gizmos").read("depth").write("color").after("sky"),
+    ]);
+    graph.sort().unwrap();
+    assert!(clears(&graph, "opaque", "color"));
+    assert!(clears(&graph, "opaque", "depth"));
+    assert!(!clears(&graph, "sky", "color"));
+    assert!(!clears(&graph, "gizmos", "color"));
+  }
+}
@@ -0,0 +1,122 @@
+use glam::{Vec3, Vec4};
+use serde::{Serialize, Deserialize};
+use miau_shared::{Light, MAX_LIGHTS, LIGHT_DIRECTIONAL, LIGHT_POINT, LIGHT_SPOT, NO_SHADOW};
+use log::warn;
+use crate::Result;
+use crate::ecs::{World, component};
+use crate::gfx::{Renderer, Binding, Bindable};
+use crate::scene::Transform;
+
+pub use miau_shared::Lights;
+
+#[component]
+#[derive(Serialize, Deserialize)]
+pub struct DirectionalLight {
+  pub direction: Vec3,
+  pub color: Vec3,
+  pub intensity: f32,
+}
+
+#[component]
+#[derive(Serialize, Deserialize)]
+pub struct PointLight {
+  pub color: Vec3,
+  pub intensity: f32,
+  pub range: f32,
+}
+
+#[component]
+#[derive(Serialize, Deserialize)]
+pub struct SpotLight {
+  pub direction: Vec3,
+  pub color: Vec3,
+  pub intensity: f32,
+  pub range: f32,
+  pub inner_angle: f32,
+  pub outer_angle: f32,
+}
+
+pub struct AmbientLight(pub Vec3);
+
+pub(crate) fn collect(world: &World) -> Result {
+  let mut lights = vec![];
+  let directional = world.get::<DirectionalLight>();
+  // the shadow pass renders the first directional light
+  let shadowed = match directional.is_empty() {
+    true => NO_SHADOW,
+    false => 0,
+  };
+  for (_, l) in directional {
+    lights.push(Light {
+      pos: Vec3::ZERO.extend(LIGHT_DIRECTIONAL),
+      dir: l.direction.extend(0.0),
+      color: l.color.extend(l.intensity),
+      cone: Vec4::ZERO,
+    });
+  }
+  for (e, l) in world.get::<PointLight>() {
+    if let Some(t) = e.get_one::<Transform>() {
+      lights.push(Light {
+        pos: t.position.extend(LIGHT_POINT),
+        dir: Vec3::ZERO.extend(l.range),
+        color: l.color.extend(l.intensity),
+        cone: Vec4::ZERO,
+      });
+    }
+  }
+  for (e, l) in world.get::<SpotLight>() {
+    if let Some(t) = e.get_one::<Transform>() {
+      lights.push(Light {
+        pos: t.position.extend(LIGHT_SPOT),
+        dir: l.direction.extend(l.range),
+        color: l.color.extend(l.intensity),
+        cone: Vec4::new(
+          l.inner_angle.to_radians().cos(),
+          l.outer_angle.to_radians().cos(),
+          0.0,
+          0.0,
+        ),
+      });
+    }
+  }
+  if lights.len() > MAX_LIGHTS {
+    warn!(
+      "{} lights in scene, only {} will be drawn.",
+      lights.len(),
+      MAX_LIGHTS
+    );
+    lights.truncate(MAX_LIGHTS);
+  }
+
+  let renderer = world.get_resource::<Renderer>().unwrap();
+  let binding = world.get_resource_mut::<Binding<Lights>>().unwrap();
+  let data = binding.data_mut();
+  data.ambient = world
+    .get_resource::<AmbientLight>()
+    .map_or(Vec3::splat(0.03), |a| a.0);
+  data.count = lights.len() as _;
+  data.shadowed = shadowed;
+  data.lights[..lights.len()].copy_from_slice(&lights);
+  binding.update(&renderer.queue);
+  Ok(())
+}
+
+pub(crate) fn empty() -> Lights {
+  Lights {
+    ambient: Vec3::ZERO,
+    count: 0,
+    shadowed: NO_SHADOW,
+    lights: [Light {
+      pos: Vec4::ZERO,
+      dir: Vec4::ZERO,
+      color: Vec4::ZERO,
+      cone: Vec4::ZERO,
+    }; MAX_LIGHTS],
+  }
+}
+
+impl Bindable for Lights {
+  fn get_layout(world: &World) -> &wgpu::BindGroupLayout {
+    &world.get_resource::<Renderer>().unwrap().light_layout
+  }
+}
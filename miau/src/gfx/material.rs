@@ -0,0 +1,206 @@
+use glam::{Vec3, Vec4};
+use serde::{Serialize, Deserialize};
+use wgpu::util::DeviceExt;
+use miau_shared::MaterialConst;
+use crate::Result;
+use crate::ecs::World;
+use crate::world;
+use crate::assets::{asset, Assets, Handle};
+use crate::gfx::{Renderer, Texture, cast};
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum AlphaMode {
+  Opaque,
+  Mask(f32),
+  Blend,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialDesc {
+  pub base_color: Vec4,
+  pub base_color_texture: Option<Handle<Texture>>,
+  pub normal_texture: Option<Handle<Texture>>,
+  pub metallic: f32,
+  pub roughness: f32,
+  pub metallic_roughness_texture: Option<Handle<Texture>>,
+  pub emissive: Vec3,
+  pub emissive_texture: Option<Handle<Texture>>,
+  pub alpha_mode: AlphaMode,
+}
+
+impl Default for MaterialDesc {
+  fn default() -> Self {
+    Self {
+      base_color: Vec4::ONE,
+      base_color_texture: None,
+      normal_texture: None,
+      metallic: 0.0,
+      roughness: 0.5,
+      metallic_roughness_texture: None,
+      emissive: Vec3::ZERO,
+      emissive_texture: None,
+      alpha_mode: AlphaMode::Opaque,
+    }
+  }
+}
+
+#[asset(Material::load)]
+pub struct Material {
+  pub desc: MaterialDesc,
+  pub buf: wgpu::Buffer,
+  pub bind_group: wgpu::BindGroup,
+}
+
+impl Material {
+  pub fn new(desc: MaterialDesc) -> Self {
+    let renderer = Renderer::get();
+    let fallback = Fallback::get();
+    let buf = renderer
+      .device
+      .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        contents: cast(&desc.consts()),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        label: None,
+      });
+    let textures = [
+      desc
+        .base_color_texture
+        .as_deref()
+        .unwrap_or(&fallback.white),
+      desc.normal_texture.as_deref().unwrap_or(&fallback.normal),
+      desc
+        .metallic_roughness_texture
+        .as_deref()
+        .unwrap_or(&fallback.white),
+      desc.emissive_texture.as_deref().unwrap_or(&fallback.white),
+    ];
+    let mut entries = vec![wgpu::BindGroupEntry {
+      binding: 0,
+      resource: buf.as_entire_binding(),
+    }];
+    for (i, t) in textures.iter().enumerate() {
+      entries.push(wgpu::BindGroupEntry {
+        binding: 1 + 2 * i as u32,
+        resource: wgpu::BindingResource::TextureView(&t.view),
+      });
+      entries.push(wgpu::BindGroupEntry {
+        binding: 2 + 2 * i as u32,
+        resource: wgpu::BindingResource::Sampler(&t.sampler),
+      });
+    }
+    let bind_group = renderer
+      .device
+      .create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &renderer.material_layout,
+        entries: &entries,
+        label: None,
+      });
+    Self {
+      desc,
+      buf,
+      bind_group,
+    }
+  }
+
+  // normal and metallic roughness maps are always linear, they're labeled sub-assets
+  // so the same image can still be loaded as srgb elsewhere
+  fn load(data: &[u8]) -> Result<Self> {
+    let file: MaterialFile = serde_json::from_slice(data)?;
+    let assets = world().get_resource::<Assets>().unwrap();
+    let linear = |path: Option<String>, label| {
+      path
+        .map(|p| assets.label(label, Texture::load_linear(&p)?))
+        .transpose()
+    };
+    Ok(Self::new(MaterialDesc {
+      normal_texture: linear(file.normal_texture, "normal_texture")?,
+      metallic_roughness_texture: linear(
+        file.metallic_roughness_texture,
+        "metallic_roughness_texture",
+      )?,
+      ..file.desc
+    }))
+  }
+
+  pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, n: u32) {
+    render_pass.set_bind_group(n, &self.bind_group, &[]);
+  }
+}
+
+#[derive(Deserialize)]
+struct MaterialFile {
+  #[serde(flatten)]
+  desc: MaterialDesc,
+  normal_texture: Option<String>,
+  metallic_roughness_texture: Option<String>,
+}
+
+impl MaterialDesc {
+  fn consts(&self) -> MaterialConst {
+    MaterialConst {
+      base_color: self.base_color,
+      emissive: self.emissive.extend(0.0),
+      metallic: self.metallic,
+      roughness: self.roughness,
+      alpha_cutoff: match self.alpha_mode {
+        AlphaMode::Mask(cutoff) => cutoff,
+        _ => 0.0,
+      },
+      _pad: 0.0,
+    }
+  }
+}
+
+struct Fallback {
+  white: Texture,
+  normal: Texture,
+}
+
+impl Fallback {
+  fn get() -> &'static Self {
+    crate::world().get_resource().unwrap()
+  }
+}
+
+pub(crate) fn init(world: &World) {
+  world.add_resource(Fallback {
+    white: Texture::init(1, 1, wgpu::TextureFormat::Rgba8Unorm, &[255; 4]),
+    normal: Texture::init(1, 1, wgpu::TextureFormat::Rgba8Unorm, &[128, 128, 255, 255]),
+  });
+}
+
+pub(crate) fn layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+  let mut entries = vec![wgpu::BindGroupLayoutEntry {
+    binding: 0,
+    visibility: wgpu::ShaderStages::FRAGMENT,
+    ty: wgpu::BindingType::Buffer {
+      ty: wgpu::BufferBindingType::Uniform,
+      has_dynamic_offset: false,
+      min_binding_size: None,
+    },
+    count: None,
+  }];
+  for i in 0..4 {
+    entries.push(wgpu::BindGroupLayoutEntry {
+      binding: 1 + 2 * i,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Texture {
+        multisampled: false,
+        view_dimension: wgpu::TextureViewDimension::D2,
+        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+      },
+      count: None,
+    });
+    entries.push(wgpu::BindGroupLayoutEntry {
+      binding: 2 + 2 * i,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+      count: None,
+    });
+  }
+  device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+    entries: &entries,
+    label: None,
+  })
+}
@@ -1,34 +1,80 @@
 pub mod standard;
+pub mod light;
+pub mod material;
+pub mod shadow;
+pub mod gltf;
+pub mod texture;
+pub mod skybox;
+pub mod post;
+pub mod bounds;
+pub mod graph;
+pub mod reload;
+pub mod pipeline;
+pub mod gizmos;
 
 use std::{slice, mem};
-use std::time::{Instant, Duration};
+use std::borrow::Cow;
+use std::time::Duration;
+use std::sync::OnceLock;
 use wgpu::util::DeviceExt;
 use winit::window::Window;
 use winit::dpi::PhysicalSize;
-use glam::{Vec2, Vec3, Mat4};
+use glam::{Vec2, Vec3, Vec4, Mat4};
 use obj::{Obj, TexturedVertex};
+use image::DynamicImage;
+use texture::TextureData;
 use standard::StandardPass;
+use shadow::ShadowPass;
+use skybox::SkyboxPass;
+use post::PostPass;
+use bounds::{Aabb, Sphere, Frustum, Bounds};
+use graph::{RenderGraph, TextureDesc};
+use reload::HotReload;
+use gizmos::{Gizmos, GizmoPass};
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
-use crate::ecs::{World, stage};
-use crate::assets::asset;
+use crate::ecs::{World, Entity, stage, component};
+use crate::scene::Transform;
+use crate::assets::{asset, Assets};
 use crate::ui::UiPass;
+use crate::window::WindowSettings;
 use crate::{Result, world};
+use log::{info, warn};
 
-pub use miau_shared::Vertex;
+pub use miau_shared::{Vertex, SceneConst};
+pub use pipeline::{PipelineBuilder, PipelineCache, PipelineStats};
 
-// move to renderer
-pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
-pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
-pub const SAMPLES: u32 = 4;
+pub struct RenderSettings {
+  pub format: Option<wgpu::TextureFormat>,
+  pub depth_format: wgpu::TextureFormat,
+  pub samples: u32,
+}
+
+impl RenderSettings {
+  pub fn new() -> Self {
+    Self {
+      format: None,
+      depth_format: wgpu::TextureFormat::Depth32Float,
+      samples: 4,
+    }
+  }
+}
 
 pub struct Renderer {
   pub surface: wgpu::Surface,
   pub device: wgpu::Device,
   pub queue: wgpu::Queue,
-  pub last_frame: Instant,
-  pub textures: Box<Textures>,
-  pub scene_layout: wgpu::BindGroupLayout,
   pub tex_layout: wgpu::BindGroupLayout,
+  pub light_layout: wgpu::BindGroupLayout,
+  pub material_layout: wgpu::BindGroupLayout,
+  pub shadow_layout: wgpu::BindGroupLayout,
+  pub cube_layout: wgpu::BindGroupLayout,
+  pub present_mode: wgpu::PresentMode,
+  pub present_modes: Vec<wgpu::PresentMode>,
+  pub format: wgpu::TextureFormat,
+  pub hdr_format: wgpu::TextureFormat,
+  pub depth_format: wgpu::TextureFormat,
+  pub samples: u32,
+  pub shader_caps: naga::valid::Capabilities,
 }
 
 impl Renderer {
@@ -48,7 +94,8 @@ impl Renderer {
       .request_device(
         &wgpu::DeviceDescriptor {
           features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
-            | wgpu::Features::PUSH_CONSTANTS,
+            | wgpu::Features::PUSH_CONSTANTS
+            | (adapter.features() & wgpu::Features::TEXTURE_COMPRESSION_BC),
           limits: adapter.limits(),
           label: None,
         },
@@ -56,21 +103,38 @@ impl Renderer {
       )
       .await?;
 
-    let textures = Box::new(Textures::new(&device, window.inner_size()));
+    if world.get_resource::<RenderSettings>().is_none() {
+      world.add_resource(RenderSettings::new());
+    }
+    let settings = world.get_resource::<RenderSettings>().unwrap();
+    let caps = surface.get_capabilities(&adapter);
+    let formats = caps.formats;
+    let format = settings
+      .format
+      .filter(|f| formats.contains(f))
+      .or(formats.iter().copied().find(|f| f.is_srgb()))
+      .or(formats.first().copied())
+      .ok_or("surface reports no supported formats")?;
+    let hdr_format = wgpu::TextureFormat::Rgba16Float;
+    let depth_format = settings.depth_format;
+    let color_flags = adapter.get_texture_format_features(hdr_format).flags;
+    let depth_flags = adapter.get_texture_format_features(depth_format).flags;
+    let samples = [16, 8, 4, 2]
+      .into_iter()
+      .filter(|n| *n <= settings.samples)
+      .find(|n| color_flags.sample_count_supported(*n) && depth_flags.sample_count_supported(*n))
+      .unwrap_or(1);
+    info!("Using {:?} with {}x MSAA.", format, samples);
+
+    let mut graph = RenderGraph::new(window.inner_size());
+    graph.texture(
+      "color",
+      TextureDesc::new(hdr_format).samples(samples).resolve(),
+    );
+    graph.texture("depth", TextureDesc::new(depth_format).samples(samples));
+    graph.external("surface");
+    world.add_resource(graph);
 
-    let scene_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-      entries: &[wgpu::BindGroupLayoutEntry {
-        binding: 0,
-        visibility: wgpu::ShaderStages::VERTEX,
-        ty: wgpu::BindingType::Buffer {
-          ty: wgpu::BufferBindingType::Uniform,
-          has_dynamic_offset: false,
-          min_binding_size: None,
-        },
-        count: None,
-      }],
-      label: None,
-    });
     let tex_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
       entries: &[
         wgpu::BindGroupLayoutEntry {
@@ -92,43 +156,114 @@ impl Renderer {
       ],
       label: None,
     });
+    let light_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      entries: &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        count: None,
+      }],
+      label: None,
+    });
+    let material_layout = material::layout(&device);
+    let shadow_layout = shadow::layout(&device);
+    let cube_layout = skybox::layout(&device);
+    let shader_caps = Shader::capabilities(
+      device.features(),
+      adapter.get_downlevel_capabilities().flags,
+    );
 
-    world.add_resource(Self {
+    let mut renderer = Self {
       surface,
       device,
       queue,
-      last_frame: Instant::now(),
-      textures,
-      scene_layout,
       tex_layout,
-    });
+      light_layout,
+      material_layout,
+      shadow_layout,
+      cube_layout,
+      present_mode: wgpu::PresentMode::AutoVsync,
+      present_modes: caps.present_modes,
+      format,
+      hdr_format,
+      depth_format,
+      samples,
+      shader_caps,
+    };
+    renderer.set_present_mode(world.get_resource::<WindowSettings>().unwrap().present_mode);
+    world.add_resource(renderer);
 
-    world.add_resource(StandardPass::new(world)?);
+    material::init(world);
     world.add_resource(Binding::new(SceneConst {
       cam: Mat4::IDENTITY,
       size: Vec2::ZERO,
+      eye: Vec4::ZERO,
     }));
+    world.add_resource(Binding::new(light::empty()));
+    world.add_system(stage::PRE_DRAW, light::collect);
+    world.add_system(stage::PRE_DRAW, Bounds::update);
+    world.add_system(stage::PRE_DRAW, HotReload::update);
+    world.add_resource(PipelineCache::new());
+    world.add_resource(ShadowPass::new(world)?);
+    world.add_resource(StandardPass::new(world)?);
+    world.add_resource(SkyboxPass::new(world)?);
+    world.add_resource(PostPass::new(world)?);
+    world.add_resource(Gizmos::new());
+    world.add_resource(GizmoPass::new(world)?);
     world.add_resource(UiPass::new(world)?);
     Ok(())
   }
 
+  // falls back to vsync if the surface can't present with it, takes effect on the next resize
+  // grows the buffer to a power of two when the data doesn't fit, so a scene gaining an
+  // entity every frame doesn't reallocate it every frame
+  pub(crate) fn write_growing(&self, buf: &mut wgpu::Buffer, data: &[u8]) {
+    if buf.size() < data.len() as u64 {
+      *buf = self.device.create_buffer(&wgpu::BufferDescriptor {
+        size: (data.len() as u64).next_power_of_two(),
+        usage: buf.usage(),
+        mapped_at_creation: false,
+        label: None,
+      });
+    }
+    self.queue.write_buffer(buf, 0, data);
+  }
+
+  pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+    self.present_mode = match present_mode {
+      wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => present_mode,
+      m if self.present_modes.contains(&m) => m,
+      m => {
+        warn!("{:?} is not supported by the surface, using AutoVsync.", m);
+        wgpu::PresentMode::AutoVsync
+      }
+    };
+  }
+
   pub fn resize(&mut self, size: PhysicalSize<u32>) {
-    self.textures = Box::new(Textures::new(&self.device, size));
+    world()
+      .get_resource_mut::<RenderGraph>()
+      .unwrap()
+      .resize(size);
     self.surface.configure(
       &self.device,
       &wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        format: FORMAT,
+        format: self.format,
         width: size.width,
         height: size.height,
-        present_mode: wgpu::PresentMode::AutoVsync,
+        present_mode: self.present_mode,
         alpha_mode: wgpu::CompositeAlphaMode::Auto,
         view_formats: vec![],
       },
     );
   }
 
-  pub fn frame(&mut self, world: &World) {
+  pub fn frame(&mut self, world: &World) -> Result {
     let encoder = Box::new(
       self
         .device
@@ -138,32 +273,47 @@ impl Renderer {
     let surface_view = surface
       .texture
       .create_view(&wgpu::TextureViewDescriptor::default());
+    let aspect = surface.texture.width() as f32 / surface.texture.height() as f32;
+    let view = match Camera::active(world) {
+      Some(e) => {
+        let camera = e.get_one::<Camera>().unwrap();
+        let m = Transform::model_matrix(world, &e).unwrap();
+        let eye = m.transform_point3(Vec3::ZERO);
+        View::new(
+          eye,
+          eye + m.transform_vector3(Vec3::Z),
+          camera.fov,
+          aspect,
+          camera.near,
+        )
+      }
+      None => View::new(Vec3::splat(5.0), Vec3::ZERO, 1.4, aspect, 0.01),
+    };
     let scene_consts = world.get_resource_mut::<Binding<SceneConst>>().unwrap();
     *scene_consts.data_mut() = SceneConst {
-      cam: Mat4::perspective_infinite_lh(
-        1.4,
-        surface.texture.width() as f32 / surface.texture.height() as f32,
-        0.01,
-      ) * Mat4::look_at_lh(Vec3::splat(5.0), Vec3::ZERO, Vec3::Y),
+      cam: view.proj * view.view,
       size: Vec2::new(surface.texture.width() as _, surface.texture.height() as _),
+      eye: view.eye.extend(1.0),
     };
     scene_consts.update(&self.queue);
+    world.add_resource(view);
     world.add_resource(Frame {
       surface,
       surface_view,
       encoder: Box::leak(encoder),
     });
-    world.add_resource(DeltaTime(Instant::now() - self.last_frame));
-    self.last_frame = Instant::now();
     world.run_system(stage::PRE_DRAW);
     world.run_system(stage::DRAW);
+    RenderGraph::execute(world)?;
     world.run_system(stage::POST_DRAW);
+    world.get_resource_mut::<Gizmos>().unwrap().clear();
 
     let frame = world.take_resource::<Frame>().unwrap();
     self
       .queue
       .submit([unsafe { Box::from_raw(frame.encoder) }.finish()]);
     frame.surface.present();
+    Ok(())
   }
 
   fn get() -> &'static Self {
@@ -171,37 +321,67 @@ impl Renderer {
   }
 }
 
-pub struct Textures {
-  pub fb: wgpu::TextureView,
-  pub depth: wgpu::TextureView,
+// looks down the entity's +z, the first active one with a Transform renders the scene
+#[component]
+#[derive(Serialize, Deserialize)]
+pub struct Camera {
+  pub fov: f32,
+  pub near: f32,
+  pub active: bool,
 }
 
-impl Textures {
-  fn new(device: &wgpu::Device, size: PhysicalSize<u32>) -> Self {
-    let desc = wgpu::TextureDescriptor {
-      size: wgpu::Extent3d {
-        width: size.width,
-        height: size.height,
-        depth_or_array_layers: 1,
-      },
-      mip_level_count: 1,
-      sample_count: SAMPLES,
-      dimension: wgpu::TextureDimension::D2,
-      format: FORMAT,
-      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-      view_formats: &[],
-      label: None,
-    };
+impl Camera {
+  pub fn new() -> Self {
     Self {
-      fb: device
-        .create_texture(&desc)
-        .create_view(&wgpu::TextureViewDescriptor::default()),
-      depth: device
-        .create_texture(&wgpu::TextureDescriptor {
-          format: DEPTH_FORMAT,
-          ..desc
-        })
-        .create_view(&wgpu::TextureViewDescriptor::default()),
+      fov: 1.4,
+      near: 0.01,
+      active: true,
+    }
+  }
+
+  pub fn fov(mut self, fov: f32) -> Self {
+    self.fov = fov;
+    self
+  }
+
+  pub fn near(mut self, near: f32) -> Self {
+    self.near = near;
+    self
+  }
+
+  pub fn active(world: &World) -> Option<Entity> {
+    world
+      .get::<Camera>()
+      .into_iter()
+      .find(|(e, c)| c.active && e.get_one::<Transform>().is_some())
+      .map(|(e, _)| e)
+  }
+}
+
+pub struct View {
+  pub eye: Vec3,
+  pub forward: Vec3,
+  pub view: Mat4,
+  pub proj: Mat4,
+  pub fov: f32,
+  pub aspect: f32,
+  pub near: f32,
+  pub frustum: Frustum,
+}
+
+impl View {
+  pub fn new(eye: Vec3, target: Vec3, fov: f32, aspect: f32, near: f32) -> Self {
+    let view = Mat4::look_at_lh(eye, target, Vec3::Y);
+    let proj = Mat4::perspective_infinite_lh(fov, aspect, near);
+    Self {
+      eye,
+      forward: (target - eye).normalize(),
+      view,
+      proj,
+      fov,
+      aspect,
+      near,
+      frustum: Frustum::from_matrix(proj * view),
     }
   }
 }
@@ -212,6 +392,101 @@ pub struct Frame<'a> {
   pub encoder: &'a mut wgpu::CommandEncoder,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Filter {
+  Nearest,
+  Linear,
+}
+
+impl From<Filter> for wgpu::FilterMode {
+  fn from(f: Filter) -> Self {
+    match f {
+      Filter::Nearest => Self::Nearest,
+      Filter::Linear => Self::Linear,
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum AddressMode {
+  Clamp,
+  Repeat,
+  Mirror,
+}
+
+impl From<AddressMode> for wgpu::AddressMode {
+  fn from(a: AddressMode) -> Self {
+    match a {
+      AddressMode::Clamp => Self::ClampToEdge,
+      AddressMode::Repeat => Self::Repeat,
+      AddressMode::Mirror => Self::MirrorRepeat,
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplerSettings {
+  pub mag_filter: Filter,
+  pub min_filter: Filter,
+  pub mipmap_filter: Filter,
+  pub address_mode_u: AddressMode,
+  pub address_mode_v: AddressMode,
+  pub anisotropy: u16,
+}
+
+impl Default for SamplerSettings {
+  fn default() -> Self {
+    Self {
+      mag_filter: Filter::Linear,
+      min_filter: Filter::Linear,
+      mipmap_filter: Filter::Linear,
+      address_mode_u: AddressMode::Repeat,
+      address_mode_v: AddressMode::Repeat,
+      anisotropy: 1,
+    }
+  }
+}
+
+impl SamplerSettings {
+  pub fn create(&self, device: &wgpu::Device) -> wgpu::Sampler {
+    let linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+      .iter()
+      .all(|f| *f == Filter::Linear);
+    device.create_sampler(&wgpu::SamplerDescriptor {
+      address_mode_u: self.address_mode_u.into(),
+      address_mode_v: self.address_mode_v.into(),
+      mag_filter: self.mag_filter.into(),
+      min_filter: self.min_filter.into(),
+      mipmap_filter: self.mipmap_filter.into(),
+      anisotropy_clamp: if linear {
+        self.anisotropy.clamp(1, 16)
+      } else {
+        1
+      },
+      ..Default::default()
+    })
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextureSettings {
+  pub srgb: bool,
+  pub mipmaps: bool,
+  pub sampler: SamplerSettings,
+}
+
+impl Default for TextureSettings {
+  fn default() -> Self {
+    Self {
+      srgb: true,
+      mipmaps: true,
+      sampler: SamplerSettings::default(),
+    }
+  }
+}
+
 #[asset(Texture::load)]
 pub struct Texture {
   pub texture: wgpu::Texture,
@@ -222,6 +497,19 @@ pub struct Texture {
 
 impl Texture {
   pub fn new(width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+    let sampler = Renderer::get()
+      .device
+      .create_sampler(&wgpu::SamplerDescriptor::default());
+    Self::with_sampler(width, height, format, 1, sampler)
+  }
+
+  pub fn with_sampler(
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    mip_level_count: u32,
+    sampler: wgpu::Sampler,
+  ) -> Self {
     let renderer = Renderer::get();
     let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
       size: wgpu::Extent3d {
@@ -229,7 +517,7 @@ impl Texture {
         height,
         depth_or_array_layers: 1,
       },
-      mip_level_count: 1,
+      mip_level_count,
       sample_count: 1,
       dimension: wgpu::TextureDimension::D2,
       format,
@@ -238,9 +526,6 @@ impl Texture {
       label: None,
     });
     let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler = renderer
-      .device
-      .create_sampler(&wgpu::SamplerDescriptor::default());
     let bind_group = renderer
       .device
       .create_bind_group(&wgpu::BindGroupDescriptor {
@@ -271,32 +556,53 @@ impl Texture {
     t
   }
 
+  pub fn from_image(img: &DynamicImage, settings: &TextureSettings) -> Self {
+    Self::from_data(&TextureData::from_image(img, settings), &settings.sampler)
+  }
+
+  pub fn from_data(data: &TextureData, sampler: &SamplerSettings) -> Self {
+    let t = Self::with_sampler(
+      data.width,
+      data.height,
+      data.format,
+      data.levels.len() as _,
+      sampler.create(&Renderer::get().device),
+    );
+    for (i, level) in data.levels.iter().enumerate() {
+      t.write_level(i as _, level);
+    }
+    t
+  }
+
   fn load(data: &[u8]) -> Result<Self> {
-    let img = image::load_from_memory(data)?;
-    Ok(Self::init(
-      img.width(),
-      img.height(),
-      wgpu::TextureFormat::Rgba8UnormSrgb,
-      &img.to_rgba8(),
-    ))
+    let settings: TextureSettings = world().get_resource::<Assets>().unwrap().meta()?;
+    Self::decode(data, &settings)
+  }
+
+  // ignores the srgb setting of the texture's meta, for data like normal maps
+  pub(crate) fn load_linear(path: &str) -> Result<Self> {
+    let assets = world().get_resource::<Assets>().unwrap();
+    let settings = TextureSettings {
+      srgb: false,
+      ..assets.meta_of(path)?
+    };
+    Self::decode(&assets.load_raw(path)?, &settings)
+  }
+
+  fn decode(data: &[u8], settings: &TextureSettings) -> Result<Self> {
+    let data = TextureData::decode(data, settings)?.supported(&Renderer::get().device);
+    if data.layers != 1 {
+      return Err("texture has more than one layer, load it as a Cubemap".into());
+    }
+    Ok(Self::from_data(&data, &settings.sampler))
   }
 
   pub fn write(&self, data: &[u8]) {
-    Renderer::get().queue.write_texture(
-      wgpu::ImageCopyTexture {
-        texture: &self.texture,
-        mip_level: 0,
-        origin: wgpu::Origin3d::ZERO,
-        aspect: wgpu::TextureAspect::All,
-      },
-      data,
-      wgpu::ImageDataLayout {
-        offset: 0,
-        bytes_per_row: Some(4 * self.texture.width()),
-        rows_per_image: Some(self.texture.height()),
-      },
-      self.texture.size(),
-    );
+    self.write_level(0, data);
+  }
+
+  pub fn write_level(&self, level: u32, data: &[u8]) {
+    texture::write_level(&Renderer::get().queue, &self.texture, level, data);
   }
 
   pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, n: u32) {
@@ -309,11 +615,14 @@ pub struct Mesh {
   pub vert_buf: wgpu::Buffer,
   pub idx_buf: wgpu::Buffer,
   pub len: u32,
+  pub aabb: Aabb,
+  pub sphere: Sphere,
 }
 
 impl Mesh {
   pub fn new(verts: &[Vertex], indices: &[u32]) -> Self {
     let device = &Renderer::get().device;
+    let points: Vec<Vec3> = verts.iter().map(|v| v.pos).collect();
     Self {
       vert_buf: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         contents: cast_slice(verts),
@@ -326,6 +635,8 @@ impl Mesh {
         label: None,
       }),
       len: indices.len() as _,
+      aabb: Aabb::from_points(points.iter().copied()),
+      sphere: Sphere::from_points(&points),
     }
   }
 
@@ -356,20 +667,159 @@ impl Mesh {
 pub struct Shader(pub wgpu::ShaderModule);
 
 impl Shader {
+  // spir-v unless the path ends in .wgsl or .glsl, glsl needs the stage before it like in sky.frag.glsl
   fn load(data: &[u8]) -> Result<Self> {
+    let path = world()
+      .get_resource::<Assets>()
+      .unwrap()
+      .path()
+      .unwrap_or_default();
+    let source = match path.rsplit('.').next() {
+      Some("wgsl" | "glsl") => wgpu::ShaderSource::Naga(Cow::Owned(Self::compile(
+        &path,
+        data,
+        Renderer::get().shader_caps,
+      )?)),
+      _ => Self::spirv(data)?,
+    };
     Ok(Self(Renderer::get().device.create_shader_module(
       wgpu::ShaderModuleDescriptor {
-        source: wgpu::util::make_spirv(data),
-        label: None,
+        source,
+        label: Some(&path),
       },
     )))
   }
+
+  // make_spirv panics on bad data, like a file build.rs is still copying during a hot reload
+  fn spirv(data: &[u8]) -> Result<wgpu::ShaderSource> {
+    if data.len() < 4 || data.len() % 4 != 0 {
+      return Err(format!("spir-v of {} bytes is truncated", data.len()).into());
+    }
+    if data[..4] != 0x0723_0203u32.to_le_bytes() {
+      return Err("spir-v is missing its magic number".into());
+    }
+    Ok(wgpu::util::make_spirv(data))
+  }
+
+  // parsed and validated here so errors point at the file and line instead of failing in wgpu
+  fn compile(path: &str, data: &[u8], caps: naga::valid::Capabilities) -> Result<naga::Module> {
+    let source = std::str::from_utf8(data)?;
+    let module = match path.rsplit('.').next() {
+      Some("wgsl") => naga::front::wgsl::parse_str(source)
+        .map_err(|e| e.emit_to_string_with_path(source, path))?,
+      _ => {
+        let stage = match path.rsplit('.').nth(1) {
+          Some("vert") => naga::ShaderStage::Vertex,
+          Some("frag") => naga::ShaderStage::Fragment,
+          Some("comp") => naga::ShaderStage::Compute,
+          _ => return Err(format!("'{}' has no .vert, .frag or .comp stage", path).into()),
+        };
+        naga::front::glsl::Frontend::default()
+          .parse(&stage.into(), source)
+          .map_err(|errors| {
+            errors
+              .iter()
+              .map(|e| {
+                let loc = e.meta.location(source);
+                format!(
+                  "{}:{}:{}: {}",
+                  path, loc.line_number, loc.line_position, e.kind
+                )
+              })
+              .collect::<Vec<_>>()
+              .join("\n")
+          })?
+      }
+    };
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), caps)
+      .validate(&module)
+      .map_err(|e| e.emit_to_string_with_path(source, path))?;
+    Ok(module)
+  }
+
+  // what the device can run, mirrors the checks wgpu does when creating the module
+  fn capabilities(
+    features: wgpu::Features,
+    downlevel: wgpu::DownlevelFlags,
+  ) -> naga::valid::Capabilities {
+    use naga::valid::Capabilities as Caps;
+    let mut caps = Caps::empty();
+    caps.set(
+      Caps::PUSH_CONSTANT,
+      features.contains(wgpu::Features::PUSH_CONSTANTS),
+    );
+    caps.set(Caps::FLOAT64, features.contains(wgpu::Features::SHADER_F64));
+    caps.set(
+      Caps::PRIMITIVE_INDEX,
+      features.contains(wgpu::Features::SHADER_PRIMITIVE_INDEX),
+    );
+    let non_uniform = features
+      .contains(wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING);
+    caps.set(
+      Caps::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+      non_uniform,
+    );
+    caps.set(Caps::SAMPLER_NON_UNIFORM_INDEXING, non_uniform);
+    caps.set(
+      Caps::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
+      features
+        .contains(wgpu::Features::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING),
+    );
+    caps.set(
+      Caps::STORAGE_TEXTURE_16BIT_NORM_FORMATS,
+      features.contains(wgpu::Features::TEXTURE_FORMAT_16BIT_NORM),
+    );
+    caps.set(
+      Caps::MULTIVIEW,
+      features.contains(wgpu::Features::MULTIVIEW),
+    );
+    caps.set(
+      Caps::EARLY_DEPTH_TEST,
+      features.contains(wgpu::Features::SHADER_EARLY_DEPTH_TEST),
+    );
+    caps.set(
+      Caps::DUAL_SOURCE_BLENDING,
+      features.contains(wgpu::Features::DUAL_SOURCE_BLENDING),
+    );
+    caps.set(
+      Caps::MULTISAMPLED_SHADING,
+      downlevel.contains(wgpu::DownlevelFlags::MULTISAMPLED_SHADING),
+    );
+    caps.set(
+      Caps::CUBE_ARRAY_TEXTURES,
+      downlevel.contains(wgpu::DownlevelFlags::CUBE_ARRAY_TEXTURES),
+    );
+    caps
+  }
 }
 
+pub use miau_macros::Bindable;
+
 pub trait Bindable {
   fn get_layout(world: &World) -> &wgpu::BindGroupLayout;
 }
 
+#[doc(hidden)]
+pub fn uniform_layout(world: &World, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayout {
+  world
+    .get_resource::<Renderer>()
+    .unwrap()
+    .device
+    .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      entries: &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        count: None,
+      }],
+      label: None,
+    })
+}
+
 pub struct Binding<T: Bindable> {
   data: T,
   dirty: bool,
@@ -436,17 +886,10 @@ impl<'de, T: Bindable + Deserialize<'de>> Deserialize<'de> for Binding<T> {
 
 pub struct DeltaTime(pub Duration);
 
-// dont duplicate
-#[repr(C)]
-#[derive(Serialize, Deserialize)]
-pub struct SceneConst {
-  cam: Mat4,
-  size: Vec2,
-}
-
 impl Bindable for SceneConst {
   fn get_layout(world: &World) -> &wgpu::BindGroupLayout {
-    &world.get_resource::<Renderer>().unwrap().scene_layout
+    static LAYOUT: OnceLock<wgpu::BindGroupLayout> = OnceLock::new();
+    LAYOUT.get_or_init(|| uniform_layout(world, wgpu::ShaderStages::VERTEX_FRAGMENT))
   }
 }
 
@@ -457,3 +900,52 @@ pub fn cast_slice<T>(t: &[T]) -> &[u8] {
 pub fn cast<T>(t: &T) -> &[u8] {
   cast_slice(slice::from_ref(t))
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn error(path: &str, source: &str) -> String {
+    Shader::compile(path, source.as_bytes(), naga::valid::Capabilities::empty())
+      .unwrap_err()
+      .to_string()
+  }
+
+  #[test]
+  fn truncated_spirv() {
+    let data = std::fs::read("../assets/miau_shaders.spv").unwrap();
+    assert!(Shader::spirv(&data).is_ok());
+    for bad in [&data[..0], &data[..2], &data[..data.len() - 3], &data[4..]] {
+      assert!(Shader::spirv(bad).is_err());
+    }
+  }
+
+  #[test]
+  fn wgsl_error_location() {
+    let source =
+      "@fragment\nfn main() -> @location(0) vec4<f32> {\n  return vec4<f32>(1.0) +;\n}\n";
+    assert!(error("shaders/broken.wgsl", source).contains("shaders/broken.wgsl:3:"));
+  }
+
+  #[test]
+  fn glsl_error_location() {
+    let source = "#version 450\nlayout(location = 0) out vec4 color;\nvoid main() {\n  color = vec4(1.0) +;\n}\n";
+    assert!(error("shaders/broken.frag.glsl", source).starts_with("shaders/broken.frag.glsl:4:"));
+  }
+
+  #[test]
+  fn validates_against_capabilities() {
+    let source = "var<push_constant> c: vec4<f32>;\n@fragment\nfn main() -> @location(0) vec4<f32> {\n  return c;\n}\n";
+    assert!(Shader::compile(
+      "a.wgsl",
+      source.as_bytes(),
+      naga::valid::Capabilities::empty()
+    )
+    .is_err());
+    let caps = Shader::capabilities(
+      wgpu::Features::PUSH_CONSTANTS,
+      wgpu::DownlevelFlags::empty(),
+    );
+    assert!(Shader::compile("a.wgsl", source.as_bytes(), caps).is_ok());
+  }
+}
@@ -0,0 +1,309 @@
+use std::mem;
+use std::rc::Rc;
+use std::ops::Range;
+use std::collections::HashMap;
+use crate::ecs::World;
+use crate::gfx::{Renderer, Shader, Vertex};
+
+const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+  wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2 => Float32x3];
+
+// render pipelines, by default drawing Vertex buffers into the hdr color and depth attachments
+#[derive(Clone)]
+pub struct PipelineBuilder<'a> {
+  shader: &'a Shader,
+  vertex: &'a str,
+  fragment: Option<&'a str>,
+  layouts: Vec<&'a wgpu::BindGroupLayout>,
+  push_constants: Vec<wgpu::PushConstantRange>,
+  mesh: bool,
+  buffers: Vec<wgpu::VertexBufferLayout<'a>>,
+  topology: wgpu::PrimitiveTopology,
+  format: Option<wgpu::TextureFormat>,
+  blend: wgpu::BlendState,
+  depth: bool,
+  depth_format: Option<wgpu::TextureFormat>,
+  depth_write: bool,
+  depth_compare: wgpu::CompareFunction,
+  depth_bias: wgpu::DepthBiasState,
+  samples: Option<u32>,
+  alpha_to_coverage: bool,
+}
+
+impl<'a> PipelineBuilder<'a> {
+  pub fn new(shader: &'a Shader, vertex: &'a str, fragment: &'a str) -> Self {
+    Self {
+      shader,
+      vertex,
+      fragment: Some(fragment),
+      layouts: vec![],
+      push_constants: vec![],
+      mesh: true,
+      buffers: vec![],
+      topology: wgpu::PrimitiveTopology::TriangleList,
+      format: None,
+      blend: wgpu::BlendState::REPLACE,
+      depth: true,
+      depth_format: None,
+      depth_write: true,
+      depth_compare: wgpu::CompareFunction::Less,
+      depth_bias: wgpu::DepthBiasState::default(),
+      samples: None,
+      alpha_to_coverage: false,
+    }
+  }
+
+  // no fragment stage or color target, like for shadow maps
+  pub fn depth_only(shader: &'a Shader, vertex: &'a str) -> Self {
+    Self {
+      fragment: None,
+      ..Self::new(shader, vertex, "")
+    }
+  }
+
+  // bind group layouts are set in the order they are added
+  pub fn layout(mut self, layout: &'a wgpu::BindGroupLayout) -> Self {
+    self.layouts.push(layout);
+    self
+  }
+
+  pub fn push_constants(mut self, stages: wgpu::ShaderStages, range: Range<u32>) -> Self {
+    self
+      .push_constants
+      .push(wgpu::PushConstantRange { stages, range });
+    self
+  }
+
+  // without the Vertex buffer in front, for fullscreen passes or custom vertices
+  pub fn no_mesh(mut self) -> Self {
+    self.mesh = false;
+    self
+  }
+
+  // extra vertex buffers after the Vertex one, like per instance data
+  pub fn buffer(mut self, buffer: wgpu::VertexBufferLayout<'a>) -> Self {
+    self.buffers.push(buffer);
+    self
+  }
+
+  pub fn topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+    self.topology = topology;
+    self
+  }
+
+  // color target, defaults to the renderer's hdr format
+  pub fn format(mut self, format: wgpu::TextureFormat) -> Self {
+    self.format = Some(format);
+    self
+  }
+
+  pub fn blend(mut self, blend: wgpu::BlendState) -> Self {
+    self.blend = blend;
+    self
+  }
+
+  pub fn no_depth(mut self) -> Self {
+    self.depth = false;
+    self
+  }
+
+  // defaults to the renderer's depth format
+  pub fn depth_format(mut self, format: wgpu::TextureFormat) -> Self {
+    self.depth_format = Some(format);
+    self
+  }
+
+  pub fn depth_write(mut self, depth_write: bool) -> Self {
+    self.depth_write = depth_write;
+    self
+  }
+
+  pub fn depth_compare(mut self, compare: wgpu::CompareFunction) -> Self {
+    self.depth_compare = compare;
+    self
+  }
+
+  pub fn depth_bias(mut self, bias: wgpu::DepthBiasState) -> Self {
+    self.depth_bias = bias;
+    self
+  }
+
+  // defaults to the renderer's msaa samples
+  pub fn samples(mut self, samples: u32) -> Self {
+    self.samples = Some(samples);
+    self
+  }
+
+  // only takes effect with msaa
+  pub fn alpha_to_coverage(mut self, alpha_to_coverage: bool) -> Self {
+    self.alpha_to_coverage = alpha_to_coverage;
+    self
+  }
+
+  // identical builders share one pipeline through the PipelineCache
+  pub fn build(&self, world: &World) -> Rc<wgpu::RenderPipeline> {
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    let cache = world.get_resource_mut::<PipelineCache>().unwrap();
+    let key = self.key(renderer);
+    if let Some(pipeline) = cache.pipelines.get(&key) {
+      cache.stats.hits += 1;
+      return pipeline.clone();
+    }
+    cache.stats.misses += 1;
+    let pipeline_layout = cache.layouts.entry(key.layout.clone()).or_insert_with(|| {
+      renderer
+        .device
+        .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+          bind_group_layouts: &self.layouts,
+          push_constant_ranges: &self.push_constants,
+          label: None,
+        })
+    });
+    let buffers: Vec<_> = key
+      .buffers
+      .iter()
+      .map(|b| wgpu::VertexBufferLayout {
+        array_stride: b.stride,
+        step_mode: b.step_mode,
+        attributes: &b.attributes,
+      })
+      .collect();
+    let targets = [key.target.clone()];
+    let pipeline = Rc::new(renderer.device.create_render_pipeline(
+      &wgpu::RenderPipelineDescriptor {
+        layout: Some(pipeline_layout),
+        vertex: wgpu::VertexState {
+          module: &self.shader.0,
+          entry_point: self.vertex,
+          buffers: &buffers,
+        },
+        fragment: self.fragment.map(|entry_point| wgpu::FragmentState {
+          module: &self.shader.0,
+          entry_point,
+          targets: &targets,
+        }),
+        primitive: key.primitive,
+        depth_stencil: key.depth_stencil.clone(),
+        multisample: key.multisample,
+        multiview: None,
+        label: None,
+      },
+    ));
+    cache.pipelines.insert(key, pipeline.clone());
+    pipeline
+  }
+
+  // the builder with the renderer defaults filled in
+  fn key(&self, renderer: &Renderer) -> PipelineKey {
+    let mesh = wgpu::VertexBufferLayout {
+      array_stride: mem::size_of::<Vertex>() as _,
+      step_mode: wgpu::VertexStepMode::Vertex,
+      attributes: &VERTEX_ATTRIBUTES,
+    };
+    let samples = self.samples.unwrap_or(renderer.samples);
+    PipelineKey {
+      layout: LayoutKey {
+        layouts: self.layouts.iter().map(|l| l.global_id()).collect(),
+        push_constants: self.push_constants.clone(),
+      },
+      shader: self.shader.0.global_id(),
+      vertex: self.vertex.to_string(),
+      fragment: self.fragment.map(str::to_string),
+      buffers: self
+        .mesh
+        .then_some(&mesh)
+        .into_iter()
+        .chain(&self.buffers)
+        .map(|b| BufferKey {
+          stride: b.array_stride,
+          step_mode: b.step_mode,
+          attributes: b.attributes.to_vec(),
+        })
+        .collect(),
+      target: self.fragment.map(|_| wgpu::ColorTargetState {
+        format: self.format.unwrap_or(renderer.hdr_format),
+        blend: Some(self.blend),
+        write_mask: wgpu::ColorWrites::ALL,
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: self.topology,
+        ..Default::default()
+      },
+      depth_stencil: self.depth.then(|| wgpu::DepthStencilState {
+        format: self.depth_format.unwrap_or(renderer.depth_format),
+        depth_write_enabled: self.depth_write,
+        depth_compare: self.depth_compare,
+        stencil: wgpu::StencilState::default(),
+        bias: self.depth_bias,
+      }),
+      multisample: wgpu::MultisampleState {
+        count: samples,
+        mask: !0,
+        alpha_to_coverage_enabled: self.alpha_to_coverage && samples > 1,
+      },
+    }
+  }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct LayoutKey {
+  layouts: Vec<wgpu::Id<wgpu::BindGroupLayout>>,
+  push_constants: Vec<wgpu::PushConstantRange>,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct BufferKey {
+  stride: wgpu::BufferAddress,
+  step_mode: wgpu::VertexStepMode,
+  attributes: Vec<wgpu::VertexAttribute>,
+}
+
+// everything that goes into the pipeline descriptor, compared in full so hash collisions
+// can't hand out the wrong pipeline
+#[derive(PartialEq, Eq, Hash)]
+struct PipelineKey {
+  layout: LayoutKey,
+  shader: wgpu::Id<wgpu::ShaderModule>,
+  vertex: String,
+  fragment: Option<String>,
+  buffers: Vec<BufferKey>,
+  target: Option<wgpu::ColorTargetState>,
+  primitive: wgpu::PrimitiveState,
+  depth_stencil: Option<wgpu::DepthStencilState>,
+  multisample: wgpu::MultisampleState,
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PipelineStats {
+  pub pipelines: usize,
+  pub layouts: usize,
+  pub hits: usize,
+  pub misses: usize,
+}
+
+// pipelines and their layouts keyed by everything that went into the builder
+#[derive(Default)]
+pub struct PipelineCache {
+  pipelines: HashMap<PipelineKey, Rc<wgpu::RenderPipeline>>,
+  layouts: HashMap<LayoutKey, wgpu::PipelineLayout>,
+  stats: PipelineStats,
+}
+
+impl PipelineCache {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn stats(&self) -> PipelineStats {
+    PipelineStats {
+      pipelines: self.pipelines.len(),
+      layouts: self.layouts.len(),
+      ..self.stats
+    }
+  }
+
+  // drops pipelines nothing uses anymore, like the ones replaced by a hot reload
+  pub fn prune(&mut self) {
+    self.pipelines.retain(|_, p| Rc::strong_count(p) > 1);
+  }
+}
@@ -0,0 +1,472 @@
+use std::mem;
+use std::rc::Rc;
+use glam::Vec4;
+use serde::{Serialize, Deserialize};
+use miau_shared::{PostConst, TONEMAP_NONE, TONEMAP_ACES, TONEMAP_FILMIC};
+use crate::Result;
+use crate::ecs::{World, component};
+use crate::assets::{Assets, Handle};
+use crate::gfx::{Renderer, Shader, Texture, Frame, Camera, PipelineBuilder, cast};
+use crate::gfx::graph::{RenderGraph, Pass, TextureDesc};
+use crate::gfx::reload::HotReload;
+
+pub const BLOOM_MIPS: u32 = 5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Tonemap {
+  None,
+  Aces,
+  Filmic,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Bloom {
+  pub intensity: f32,
+  pub threshold: f32,
+}
+
+// goes on the camera entity, without one the defaults are used
+#[component]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostProcess {
+  pub exposure: f32,
+  pub tonemap: Tonemap,
+  pub bloom: Option<Bloom>,
+  pub fxaa: bool,
+  pub vignette: f32,
+  pub lut: Option<Handle<Texture>>,
+}
+
+impl Default for PostProcess {
+  fn default() -> Self {
+    Self {
+      exposure: 1.0,
+      tonemap: Tonemap::Aces,
+      bloom: None,
+      fxaa: false,
+      vignette: 0.0,
+      lut: None,
+    }
+  }
+}
+
+impl PostProcess {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn exposure(mut self, exposure: f32) -> Self {
+    self.exposure = exposure;
+    self
+  }
+
+  pub fn tonemap(mut self, tonemap: Tonemap) -> Self {
+    self.tonemap = tonemap;
+    self
+  }
+
+  pub fn bloom(mut self, intensity: f32, threshold: f32) -> Self {
+    self.bloom = Some(Bloom {
+      intensity,
+      threshold,
+    });
+    self
+  }
+
+  pub fn fxaa(mut self, fxaa: bool) -> Self {
+    self.fxaa = fxaa;
+    self
+  }
+
+  pub fn vignette(mut self, vignette: f32) -> Self {
+    self.vignette = vignette;
+    self
+  }
+
+  pub fn lut(mut self, lut: Handle<Texture>) -> Self {
+    self.lut = Some(lut);
+    self
+  }
+}
+
+pub struct PostPass {
+  bloom_down: Rc<wgpu::RenderPipeline>,
+  bloom_up: Rc<wgpu::RenderPipeline>,
+  composite: Rc<wgpu::RenderPipeline>,
+  fxaa: Rc<wgpu::RenderPipeline>,
+  layout: wgpu::BindGroupLayout,
+  composite_layout: wgpu::BindGroupLayout,
+  sampler: wgpu::Sampler,
+  buf: wgpu::Buffer,
+  blank: Texture,
+  bind_groups: Option<BindGroups>,
+}
+
+// rebuilt when the graph's textures or the lut change
+struct BindGroups {
+  generation: u64,
+  lut: wgpu::Id<wgpu::Texture>,
+  bloom_down: Vec<wgpu::BindGroup>,
+  bloom_up: Vec<wgpu::BindGroup>,
+  composite: wgpu::BindGroup,
+  fxaa: wgpu::BindGroup,
+}
+
+impl PostPass {
+  pub fn new(world: &World) -> Result<Self> {
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    let shader = world
+      .get_resource::<Assets>()
+      .unwrap()
+      .load::<Shader>("miau_shaders.spv")?;
+    let layout = bind_group_layout(&renderer.device, 1);
+    let composite_layout = bind_group_layout(&renderer.device, 3);
+    let [bloom_down, bloom_up, composite, fxaa] =
+      Self::pipelines(world, &shader, &layout, &composite_layout);
+    HotReload::watch(
+      world,
+      "miau_shaders.spv",
+      |world, shader| {
+        let pass = world.get_resource::<Self>().unwrap();
+        Self::pipelines(world, shader, &pass.layout, &pass.composite_layout)
+      },
+      |p: &mut Self, [bloom_down, bloom_up, composite, fxaa]| {
+        p.bloom_down = bloom_down;
+        p.bloom_up = bloom_up;
+        p.composite = composite;
+        p.fxaa = fxaa;
+      },
+    );
+    let blank = Texture::new(1, 1, wgpu::TextureFormat::Rgba8Unorm);
+    blank.write(&[255; 4]);
+    let buf = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+      size: mem::size_of::<PostConst>() as _,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+      label: None,
+    });
+    let graph = world.get_resource_mut::<RenderGraph>().unwrap();
+    graph.texture("ldr", TextureDesc::new(renderer.format));
+    graph.texture(
+      "bloom",
+      TextureDesc::new(renderer.hdr_format)
+        .scale(2)
+        .mips(BLOOM_MIPS),
+    );
+    graph.add(
+      Pass::new("post", Self::pass)
+        .read("color")
+        .write("bloom")
+        .write("ldr")
+        .write("surface"),
+    );
+    Ok(Self {
+      bloom_down,
+      bloom_up,
+      composite,
+      fxaa,
+      layout,
+      composite_layout,
+      sampler: renderer.device.create_sampler(&wgpu::SamplerDescriptor {
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+      }),
+      buf,
+      blank,
+      bind_groups: None,
+    })
+  }
+
+  // bloom down, bloom up, composite and fxaa
+  fn pipelines(
+    world: &World,
+    shader: &Shader,
+    layout: &wgpu::BindGroupLayout,
+    composite_layout: &wgpu::BindGroupLayout,
+  ) -> [Rc<wgpu::RenderPipeline>; 4] {
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    let additive = wgpu::BlendState {
+      color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+      },
+      alpha: wgpu::BlendComponent::REPLACE,
+    };
+    let pipeline = |layout, entry_point, format, blend| {
+      PipelineBuilder::new(shader, "post_v", entry_point)
+        .layout(layout)
+        .push_constants(wgpu::ShaderStages::FRAGMENT, 0..mem::size_of::<Vec4>() as _)
+        .no_mesh()
+        .format(format)
+        .blend(blend)
+        .no_depth()
+        .samples(1)
+        .build(world)
+    };
+    [
+      pipeline(
+        layout,
+        "bloom_down_f",
+        renderer.hdr_format,
+        wgpu::BlendState::REPLACE,
+      ),
+      pipeline(layout, "bloom_up_f", renderer.hdr_format, additive),
+      pipeline(
+        composite_layout,
+        "post_f",
+        renderer.format,
+        wgpu::BlendState::REPLACE,
+      ),
+      pipeline(layout, "fxaa_f", renderer.format, wgpu::BlendState::REPLACE),
+    ]
+  }
+
+  fn pass(world: &World) -> Result {
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    let frame = world.get_resource_mut::<Frame>().unwrap();
+    let pass = world.get_resource_mut::<PostPass>().unwrap();
+    let camera = Camera::active(world);
+    let post = camera.as_ref().and_then(|e| e.get_one::<PostProcess>());
+    let default = PostProcess::default();
+    let settings = post.as_deref().unwrap_or(&default);
+    let graph = world.get_resource::<RenderGraph>().unwrap();
+    let size = frame.surface.texture.size();
+    let texel = |level: u32| {
+      Vec4::new(
+        1.0 / (size.width >> level).max(1) as f32,
+        1.0 / (size.height >> level).max(1) as f32,
+        0.0,
+        0.0,
+      )
+    };
+
+    let lut = settings.lut.as_ref().map_or(&pass.blank, |l| &**l);
+    renderer.queue.write_buffer(
+      &pass.buf,
+      0,
+      cast(&PostConst {
+        params: Vec4::new(
+          settings.exposure,
+          match settings.tonemap {
+            Tonemap::None => TONEMAP_NONE,
+            Tonemap::Aces => TONEMAP_ACES,
+            Tonemap::Filmic => TONEMAP_FILMIC,
+          },
+          settings.bloom.map_or(0.0, |b| b.intensity),
+          settings.vignette,
+        ),
+        lut: Vec4::new(
+          settings
+            .lut
+            .as_ref()
+            .map_or(0.0, |_| lut.texture.height() as f32),
+          0.0,
+          0.0,
+          0.0,
+        ),
+        texel: texel(0),
+      }),
+    );
+
+    let key = (graph.generation(), lut.texture.global_id());
+    if pass
+      .bind_groups
+      .as_ref()
+      .map_or(true, |b| (b.generation, b.lut) != key)
+    {
+      let bind_groups = pass.bind_groups(renderer, graph, lut);
+      pass.bind_groups = Some(bind_groups);
+    }
+    let bind_groups = pass.bind_groups.as_ref().unwrap();
+
+    if let Some(bloom) = settings.bloom {
+      for i in 0..graph.mips("bloom") {
+        let params = texel(i as _) + Vec4::new(0.0, 0.0, bloom.threshold, (i == 0) as u32 as f32);
+        pass.draw(
+          frame.encoder,
+          graph.mip("bloom", i),
+          &pass.bloom_down,
+          &bind_groups.bloom_down[i],
+          Some(params),
+        );
+      }
+      for i in (1..graph.mips("bloom")).rev() {
+        pass.draw(
+          frame.encoder,
+          graph.mip("bloom", i - 1),
+          &pass.bloom_up,
+          &bind_groups.bloom_up[i - 1],
+          Some(texel(i as u32 + 1)),
+        );
+      }
+    }
+
+    let encoder = &mut *frame.encoder;
+    if settings.fxaa {
+      pass.draw(
+        encoder,
+        graph.view("ldr"),
+        &pass.composite,
+        &bind_groups.composite,
+        None,
+      );
+      pass.draw(
+        encoder,
+        &frame.surface_view,
+        &pass.fxaa,
+        &bind_groups.fxaa,
+        None,
+      );
+    } else {
+      pass.draw(
+        encoder,
+        &frame.surface_view,
+        &pass.composite,
+        &bind_groups.composite,
+        None,
+      );
+    }
+    Ok(())
+  }
+
+  fn bind_groups(&self, renderer: &Renderer, graph: &RenderGraph, lut: &Texture) -> BindGroups {
+    let mips = graph.mips("bloom");
+    let composite = renderer
+      .device
+      .create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &self.composite_layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: self.buf.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(graph.view("color")),
+          },
+          wgpu::BindGroupEntry {
+            binding: 2,
+            resource: wgpu::BindingResource::Sampler(&self.sampler),
+          },
+          wgpu::BindGroupEntry {
+            binding: 3,
+            resource: wgpu::BindingResource::TextureView(graph.mip("bloom", 0)),
+          },
+          wgpu::BindGroupEntry {
+            binding: 4,
+            resource: wgpu::BindingResource::TextureView(&lut.view),
+          },
+        ],
+        label: None,
+      });
+    BindGroups {
+      generation: graph.generation(),
+      lut: lut.texture.global_id(),
+      bloom_down: (0..mips)
+        .map(|i| match i {
+          0 => self.bind(renderer, graph.view("color")),
+          _ => self.bind(renderer, graph.mip("bloom", i - 1)),
+        })
+        .collect(),
+      bloom_up: (1..mips)
+        .map(|i| self.bind(renderer, graph.mip("bloom", i)))
+        .collect(),
+      composite,
+      fxaa: self.bind(renderer, graph.view("ldr")),
+    }
+  }
+
+  fn bind(&self, renderer: &Renderer, view: &wgpu::TextureView) -> wgpu::BindGroup {
+    renderer
+      .device
+      .create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &self.layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: self.buf.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(view),
+          },
+          wgpu::BindGroupEntry {
+            binding: 2,
+            resource: wgpu::BindingResource::Sampler(&self.sampler),
+          },
+        ],
+        label: None,
+      })
+  }
+
+  fn draw(
+    &self,
+    encoder: &mut wgpu::CommandEncoder,
+    target: &wgpu::TextureView,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    params: Option<Vec4>,
+  ) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: target,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: wgpu::StoreOp::Store,
+        },
+      })],
+      depth_stencil_attachment: None,
+      occlusion_query_set: None,
+      timestamp_writes: None,
+      label: None,
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    if let Some(params) = params {
+      render_pass.set_push_constants(wgpu::ShaderStages::FRAGMENT, 0, cast(&params));
+    }
+    render_pass.draw(0..3, 0..1);
+  }
+}
+
+// a uniform, `textures` sampled textures and a sampler at binding 2
+fn bind_group_layout(device: &wgpu::Device, textures: u32) -> wgpu::BindGroupLayout {
+  let texture = |binding| wgpu::BindGroupLayoutEntry {
+    binding,
+    visibility: wgpu::ShaderStages::FRAGMENT,
+    ty: wgpu::BindingType::Texture {
+      multisampled: false,
+      view_dimension: wgpu::TextureViewDimension::D2,
+      sample_type: wgpu::TextureSampleType::Float { filterable: true },
+    },
+    count: None,
+  };
+  let mut entries = vec![
+    wgpu::BindGroupLayoutEntry {
+      binding: 0,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    },
+    texture(1),
+    wgpu::BindGroupLayoutEntry {
+      binding: 2,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+      count: None,
+    },
+  ];
+  entries.extend((3..textures + 2).map(texture));
+  device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+    entries: &entries,
+    label: None,
+  })
+}
@@ -0,0 +1,151 @@
+use std::fs;
+use std::any::Any;
+use std::path::PathBuf;
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime};
+use log::{info, error};
+use crate::Result;
+use crate::ecs::World;
+use crate::assets::{Assets, Handle};
+use crate::gfx::{Renderer, Shader, PipelineCache};
+
+type Rebuild = Box<dyn Fn(&World, &Shader)>;
+
+struct Watched {
+  modified: Option<SystemTime>,
+  rebuilds: Vec<Rebuild>,
+}
+
+// dev mode, add it as a resource before the engine starts to rebuild pipelines
+// when their shaders change in the assets directory
+pub struct HotReload {
+  dir: PathBuf,
+  interval: Duration,
+  last: Instant,
+  shaders: HashMap<String, Watched>,
+}
+
+impl HotReload {
+  pub fn new() -> Self {
+    Self {
+      dir: PathBuf::from("assets"),
+      interval: Duration::from_millis(500),
+      last: Instant::now(),
+      shaders: HashMap::new(),
+    }
+  }
+
+  pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+    self.dir = dir.into();
+    self
+  }
+
+  pub fn interval(mut self, interval: Duration) -> Self {
+    self.interval = interval;
+    self
+  }
+
+  // build runs with the new shader and apply only sees its result if it passed validation,
+  // does nothing without the HotReload resource
+  pub fn watch<T, P, B, A>(world: &World, shader: &str, build: B, apply: A)
+  where
+    T: Any,
+    B: Fn(&World, &Shader) -> P + 'static,
+    A: Fn(&mut T, P) + 'static,
+  {
+    let Some(reload) = world.get_resource_mut::<HotReload>() else {
+      return;
+    };
+    let modified = reload.modified(shader);
+    let watched = reload.shaders.entry(shader.to_string()).or_insert(Watched {
+      modified,
+      rebuilds: vec![],
+    });
+    let path = shader.to_string();
+    watched.rebuilds.push(Box::new(move |world, shader| {
+      let device = &world.get_resource::<Renderer>().unwrap().device;
+      device.push_error_scope(wgpu::ErrorFilter::Validation);
+      let pipelines = build(world, shader);
+      match pollster::block_on(device.pop_error_scope()) {
+        Some(e) => error!("Keeping the old pipeline for '{}': {}", path, e),
+        None => match world.get_resource_mut::<T>() {
+          Some(pass) => apply(pass, pipelines),
+          None => error!(
+            "No {} to reload '{}' into.",
+            std::any::type_name::<T>(),
+            path
+          ),
+        },
+      }
+    }));
+  }
+
+  pub(crate) fn update(world: &World) -> Result {
+    let Some(reload) = world.get_resource_mut::<HotReload>() else {
+      return Ok(());
+    };
+    if reload.last.elapsed() < reload.interval {
+      return Ok(());
+    }
+    reload.last = Instant::now();
+    let mut changed = vec![];
+    for (path, watched) in &reload.shaders {
+      let modified = reload.modified(path);
+      if modified.is_some() && modified != watched.modified {
+        changed.push((path.clone(), modified));
+      }
+    }
+    for (path, modified) in changed {
+      reload.shaders.get_mut(&path).unwrap().modified = modified;
+      let Some(shader) = reload.load(world, &path) else {
+        continue;
+      };
+      info!("Reloaded shader '{}'.", path);
+      for rebuild in &reload.shaders[&path].rebuilds {
+        rebuild(world, &shader);
+      }
+      world.get_resource_mut::<PipelineCache>().unwrap().prune();
+    }
+    Ok(())
+  }
+
+  fn load(&self, world: &World, path: &str) -> Option<Handle<Shader>> {
+    let data = match fs::read(self.dir.join(path)) {
+      Ok(data) => data,
+      Err(e) => {
+        error!("Could not read shader '{}': {}", path, e);
+        return None;
+      }
+    };
+    let device = &world.get_resource::<Renderer>().unwrap().device;
+    let assets = world.get_resource::<Assets>().unwrap();
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let shader = assets.load_from::<Shader>(path, &data);
+    let shader = match pollster::block_on(device.pop_error_scope()) {
+      Some(e) => Err(e.to_string().into()),
+      None => shader,
+    };
+    match shader {
+      Ok(shader) => {
+        assets.replace(&shader);
+        Some(shader)
+      }
+      Err(e) => {
+        error!("Keeping the old shader for '{}': {}", path, e);
+        None
+      }
+    }
+  }
+
+  fn modified(&self, path: &str) -> Option<SystemTime> {
+    fs::metadata(self.dir.join(path))
+      .and_then(|m| m.modified())
+      .ok()
+  }
+}
+
+impl Default for HotReload {
+  fn default() -> Self {
+    Self::new()
+  }
+}
@@ -0,0 +1,287 @@
+use std::mem;
+use std::rc::Rc;
+use glam::{Vec3, Vec4, Mat4};
+use serde::{Serialize, Deserialize};
+use miau_shared::{ShadowConst, ShadowPush, CASCADES};
+use crate::Result;
+use crate::ecs::{World, component};
+use crate::assets::{Assets, Handle};
+use crate::gfx::{Renderer, Mesh, Shader, Frame, View, PipelineBuilder, cast};
+use crate::gfx::graph::{RenderGraph, Pass};
+use crate::gfx::reload::HotReload;
+use crate::gfx::light::DirectionalLight;
+use crate::scene::{Transform, Model};
+
+pub const SHADOW_SIZE: u32 = 2048;
+
+#[component]
+#[derive(Serialize, Deserialize)]
+pub struct ShadowCaster(pub Handle<Mesh>);
+
+pub struct ShadowPass {
+  pub distance: f32,
+  pub bias: f32,
+  pipeline: Rc<wgpu::RenderPipeline>,
+  views: Vec<wgpu::TextureView>,
+  consts: ShadowConst,
+  buf: wgpu::Buffer,
+  bind_group: wgpu::BindGroup,
+}
+
+impl ShadowPass {
+  pub fn new(world: &World) -> Result<Self> {
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    let shader = world
+      .get_resource::<Assets>()
+      .unwrap()
+      .load::<Shader>("miau_shaders.spv")?;
+    let pipeline = Self::pipeline(world, &shader);
+    HotReload::watch(
+      world,
+      "miau_shaders.spv",
+      Self::pipeline,
+      |p: &mut Self, pipeline| p.pipeline = pipeline,
+    );
+
+    let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+      size: wgpu::Extent3d {
+        width: SHADOW_SIZE,
+        height: SHADOW_SIZE,
+        depth_or_array_layers: CASCADES as _,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: wgpu::TextureFormat::Depth32Float,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+      view_formats: &[],
+      label: None,
+    });
+    let views = (0..CASCADES as u32)
+      .map(|i| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+          dimension: Some(wgpu::TextureViewDimension::D2),
+          base_array_layer: i,
+          array_layer_count: Some(1),
+          ..Default::default()
+        })
+      })
+      .collect();
+    let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+      dimension: Some(wgpu::TextureViewDimension::D2Array),
+      ..Default::default()
+    });
+    let sampler = renderer.device.create_sampler(&wgpu::SamplerDescriptor {
+      mag_filter: wgpu::FilterMode::Linear,
+      min_filter: wgpu::FilterMode::Linear,
+      compare: Some(wgpu::CompareFunction::LessEqual),
+      ..Default::default()
+    });
+    let consts = ShadowConst {
+      cascades: [Mat4::IDENTITY; CASCADES],
+      splits: Vec4::ZERO,
+      forward: Vec4::ZERO,
+      params: Vec4::ZERO,
+    };
+    let buf = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+      size: mem::size_of::<ShadowConst>() as _,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+      label: None,
+    });
+    let bind_group = renderer
+      .device
+      .create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &renderer.shadow_layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buf.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(&array_view),
+          },
+          wgpu::BindGroupEntry {
+            binding: 2,
+            resource: wgpu::BindingResource::Sampler(&sampler),
+          },
+        ],
+        label: None,
+      });
+    let graph = world.get_resource_mut::<RenderGraph>().unwrap();
+    graph.external("shadow");
+    graph.add(Pass::new("shadow", Self::pass).write("shadow"));
+    Ok(Self {
+      distance: 50.0,
+      bias: 0.001,
+      pipeline,
+      views,
+      consts,
+      buf,
+      bind_group,
+    })
+  }
+
+  fn pipeline(world: &World, shader: &Shader) -> Rc<wgpu::RenderPipeline> {
+    PipelineBuilder::depth_only(shader, "shadow_v")
+      .push_constants(
+        wgpu::ShaderStages::VERTEX,
+        0..mem::size_of::<ShadowPush>() as _,
+      )
+      .depth_format(wgpu::TextureFormat::Depth32Float)
+      .depth_bias(wgpu::DepthBiasState {
+        constant: 2,
+        slope_scale: 2.0,
+        clamp: 0.0,
+      })
+      .samples(1)
+      .build(world)
+  }
+
+  fn pass(world: &World) -> Result {
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    let frame = world.get_resource_mut::<Frame>().unwrap();
+    let pass = world.get_resource_mut::<ShadowPass>().unwrap();
+    let view = world.get_resource::<View>().unwrap();
+
+    // the first directional light casts shadows, light::collect marks it in Lights
+    let lights = world.get::<DirectionalLight>();
+    let Some((_, light)) = lights.first() else {
+      pass.consts.forward = Vec4::ZERO;
+      renderer.queue.write_buffer(&pass.buf, 0, cast(&pass.consts));
+      return Ok(());
+    };
+    pass.update(view, light.direction.normalize());
+    renderer
+      .queue
+      .write_buffer(&pass.buf, 0, cast(&pass.consts));
+
+    let models = world.get::<Model>();
+    let shadow_casters = world.get::<ShadowCaster>();
+    let mut casters = vec![];
+    for (e, model) in &models {
+      if let Some(m) = Transform::model_matrix(world, e) {
+        casters.push((m, &*model.mesh));
+      }
+    }
+    for (e, caster) in &shadow_casters {
+      if let Some(m) = Transform::model_matrix(world, e) {
+        casters.push((m, &*caster.0));
+      }
+    }
+
+    for (i, target) in pass.views.iter().enumerate() {
+      let mut render_pass = frame
+        .encoder
+        .begin_render_pass(&wgpu::RenderPassDescriptor {
+          color_attachments: &[],
+          depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: target,
+            depth_ops: Some(wgpu::Operations {
+              load: wgpu::LoadOp::Clear(1.0),
+              store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+          }),
+          occlusion_query_set: None,
+          timestamp_writes: None,
+          label: None,
+        });
+      render_pass.set_pipeline(&pass.pipeline);
+      for (model, mesh) in &casters {
+        render_pass.set_push_constants(
+          wgpu::ShaderStages::VERTEX,
+          0,
+          cast(&ShadowPush {
+            light: pass.consts.cascades[i],
+            model: *model,
+          }),
+        );
+        mesh.render(&mut render_pass, 1);
+      }
+    }
+    Ok(())
+  }
+
+  fn update(&mut self, view: &View, dir: Vec3) {
+    let inv_view = view.view.inverse();
+    let tan = (view.fov / 2.0).tan();
+    let mut near = view.near;
+    let mut splits = [0.0; CASCADES];
+    for (i, split) in splits.iter_mut().enumerate() {
+      let p = (i + 1) as f32 / CASCADES as f32;
+      let far = 0.75 * view.near * (self.distance / view.near).powf(p)
+        + 0.25 * (view.near + (self.distance - view.near) * p);
+      *split = far;
+
+      let corners = [near, far].map(|z| {
+        let h = z * tan;
+        let w = h * view.aspect;
+        [(-w, -h), (w, -h), (-w, h), (w, h)]
+          .map(|(x, y)| inv_view.transform_point3(Vec3::new(x, y, z)))
+      });
+      let corners = corners.iter().flatten();
+      let center = corners.clone().sum::<Vec3>() / 8.0;
+      let radius = corners.map(|c| c.distance(center)).fold(0.0, f32::max);
+      let radius = (radius * 16.0).ceil() / 16.0;
+
+      let up = if dir.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+      let light_view = Mat4::look_at_lh(center - dir * (radius + self.distance), center, up);
+      let proj = Mat4::orthographic_lh(
+        -radius,
+        radius,
+        -radius,
+        radius,
+        0.0,
+        2.0 * radius + self.distance,
+      );
+      let origin = (proj * light_view).project_point3(Vec3::ZERO) * SHADOW_SIZE as f32 / 2.0;
+      let offset = (origin.round() - origin) * 2.0 / SHADOW_SIZE as f32;
+      let snap = Mat4::from_translation(Vec3::new(offset.x, offset.y, 0.0));
+      self.consts.cascades[i] = snap * proj * light_view;
+      near = far;
+    }
+    self.consts.splits = Vec4::from_array(splits);
+    self.consts.forward = view.forward.extend(1.0);
+    self.consts.params = Vec4::new(self.bias, 1.0 / SHADOW_SIZE as f32, 0.0, 0.0);
+  }
+
+  pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, n: u32) {
+    render_pass.set_bind_group(n, &self.bind_group, &[]);
+  }
+}
+
+pub(crate) fn layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+  device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+    entries: &[
+      wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+          multisampled: false,
+          view_dimension: wgpu::TextureViewDimension::D2Array,
+          sample_type: wgpu::TextureSampleType::Depth,
+        },
+        count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+        count: None,
+      },
+    ],
+    label: None,
+  })
+}
@@ -0,0 +1,266 @@
+use std::mem;
+use std::rc::Rc;
+use glam::Vec4;
+use serde::{Serialize, Deserialize};
+use miau_shared::SkyConst;
+use crate::{Result, world};
+use crate::ecs::{World, component};
+use crate::assets::{asset, Assets, Handle};
+use crate::gfx::{
+  Renderer, Shader, Frame, View, SamplerSettings, TextureSettings, PipelineBuilder, cast,
+};
+use crate::gfx::texture::{self, TextureData};
+use crate::gfx::graph::{RenderGraph, Pass};
+use crate::gfx::reload::HotReload;
+
+#[asset(Cubemap::load)]
+pub struct Cubemap {
+  pub texture: wgpu::Texture,
+  pub view: wgpu::TextureView,
+  pub sampler: wgpu::Sampler,
+  pub bind_group: wgpu::BindGroup,
+}
+
+impl Cubemap {
+  pub fn from_data(data: &TextureData, sampler: &SamplerSettings) -> Self {
+    let renderer = Renderer::get();
+    let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+      size: wgpu::Extent3d {
+        width: data.width,
+        height: data.height,
+        depth_or_array_layers: 6,
+      },
+      mip_level_count: data.levels.len() as _,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: data.format,
+      usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+      view_formats: &[],
+      label: None,
+    });
+    for (i, level) in data.levels.iter().enumerate() {
+      texture::write_level(&renderer.queue, &texture, i as _, level);
+    }
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+      dimension: Some(wgpu::TextureViewDimension::Cube),
+      ..Default::default()
+    });
+    let sampler = sampler.create(&renderer.device);
+    let bind_group = renderer
+      .device
+      .create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &renderer.cube_layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&view),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::Sampler(&sampler),
+          },
+        ],
+        label: None,
+      });
+    Self {
+      texture,
+      view,
+      sampler,
+      bind_group,
+    }
+  }
+
+  fn load(data: &[u8]) -> Result<Self> {
+    let assets = world().get_resource::<Assets>().unwrap();
+    let settings: TextureSettings = assets.meta()?;
+    let data = match data {
+      d if d.starts_with(b"[") => {
+        let faces: Vec<String> = serde_json::from_slice(d)?;
+        TextureData::faces(
+          &faces
+            .iter()
+            .map(|f| {
+              Ok(image::load_from_memory(
+                &assets.load_raw(&assets.relative(f))?,
+              )?)
+            })
+            .collect::<Result<Vec<_>>>()?,
+          &settings,
+        )?
+      }
+      d if TextureData::is_container(d) => TextureData::decode(d, &settings)?,
+      d => TextureData::equirect(&image::load_from_memory(d)?, &settings),
+    };
+    if data.layers != 6 {
+      return Err("cubemap needs 6 faces".into());
+    }
+    Ok(Self::from_data(
+      &data.supported(&Renderer::get().device),
+      &settings.sampler,
+    ))
+  }
+
+  pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, n: u32) {
+    render_pass.set_bind_group(n, &self.bind_group, &[]);
+  }
+}
+
+#[component]
+#[derive(Serialize, Deserialize)]
+pub struct Skybox {
+  pub cubemap: Handle<Cubemap>,
+  pub intensity: f32,
+}
+
+impl Skybox {
+  pub fn new(cubemap: Handle<Cubemap>) -> Self {
+    Self {
+      cubemap,
+      intensity: 1.0,
+    }
+  }
+
+  pub fn intensity(mut self, intensity: f32) -> Self {
+    self.intensity = intensity;
+    self
+  }
+}
+
+pub struct SkyboxPass {
+  pipeline: Rc<wgpu::RenderPipeline>,
+  layout: wgpu::BindGroupLayout,
+  buf: wgpu::Buffer,
+  bind_group: wgpu::BindGroup,
+}
+
+impl SkyboxPass {
+  pub fn new(world: &World) -> Result<Self> {
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    let shader = world
+      .get_resource::<Assets>()
+      .unwrap()
+      .load::<Shader>("miau_shaders.spv")?;
+    let layout = renderer
+      .device
+      .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        }],
+        label: None,
+      });
+    let pipeline = Self::pipeline(world, &shader, &layout);
+    HotReload::watch(
+      world,
+      "miau_shaders.spv",
+      |world, shader| Self::pipeline(world, shader, &world.get_resource::<Self>().unwrap().layout),
+      |p: &mut Self, pipeline| p.pipeline = pipeline,
+    );
+    let buf = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+      size: mem::size_of::<SkyConst>() as _,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+      label: None,
+    });
+    let bind_group = renderer
+      .device
+      .create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &layout,
+        entries: &[wgpu::BindGroupEntry {
+          binding: 0,
+          resource: buf.as_entire_binding(),
+        }],
+        label: None,
+      });
+    world
+      .get_resource_mut::<RenderGraph>()
+      .unwrap()
+      .add(Pass::new("skybox", Self::pass).read("depth").write("color"));
+    Ok(Self {
+      pipeline,
+      layout,
+      buf,
+      bind_group,
+    })
+  }
+
+  fn pipeline(
+    world: &World,
+    shader: &Shader,
+    layout: &wgpu::BindGroupLayout,
+  ) -> Rc<wgpu::RenderPipeline> {
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    PipelineBuilder::new(shader, "sky_v", "sky_f")
+      .layout(layout)
+      .layout(&renderer.cube_layout)
+      .no_mesh()
+      .depth_write(false)
+      .depth_compare(wgpu::CompareFunction::LessEqual)
+      .build(world)
+  }
+
+  fn pass(world: &World) -> Result {
+    let skyboxes = world.get::<Skybox>();
+    let Some((_, skybox)) = skyboxes.first() else {
+      return Ok(());
+    };
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    let frame = world.get_resource_mut::<Frame>().unwrap();
+    let pass = world.get_resource::<SkyboxPass>().unwrap();
+    let view = world.get_resource::<View>().unwrap();
+    let graph = world.get_resource::<RenderGraph>().unwrap();
+    renderer.queue.write_buffer(
+      &pass.buf,
+      0,
+      cast(&SkyConst {
+        inv_view_proj: (view.proj * view.view).inverse(),
+        params: Vec4::new(skybox.intensity, 0.0, 0.0, 0.0),
+      }),
+    );
+
+    let mut render_pass = frame
+      .encoder
+      .begin_render_pass(&wgpu::RenderPassDescriptor {
+        color_attachments: &[Some(graph.color_attachment("color"))],
+        depth_stencil_attachment: Some(graph.depth_attachment("depth")),
+        occlusion_query_set: None,
+        timestamp_writes: None,
+        label: None,
+      });
+    render_pass.set_pipeline(&pass.pipeline);
+    render_pass.set_bind_group(0, &pass.bind_group, &[]);
+    skybox.cubemap.bind(&mut render_pass, 1);
+    render_pass.draw(0..3, 0..1);
+    Ok(())
+  }
+}
+
+pub(crate) fn layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+  device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+    entries: &[
+      wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+          multisampled: false,
+          view_dimension: wgpu::TextureViewDimension::Cube,
+          sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+      },
+    ],
+    label: None,
+  })
+}
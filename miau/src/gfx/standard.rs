@@ -1,23 +1,40 @@
 use std::mem;
+use std::rc::Rc;
+use std::collections::HashMap;
 use glam::Mat4;
 use serde::{Serialize, Deserialize};
 use crate::Result;
-use crate::ecs::{World, stage, component};
+use crate::ecs::{World, component};
 use crate::assets::{Assets, Handle};
 use crate::gfx::{
-  Renderer, Mesh, Texture, Shader, Frame, Vertex, Binding, SceneConst, FORMAT, DEPTH_FORMAT,
-  SAMPLES, cast,
+  Bindable, Renderer, Mesh, Shader, Frame, View, Binding, SceneConst, PipelineBuilder, cast_slice,
 };
+use crate::gfx::bounds::Bounds;
+use crate::gfx::graph::{RenderGraph, Pass};
+use crate::gfx::reload::HotReload;
+use crate::gfx::light::Lights;
+use crate::gfx::material::{Material, AlphaMode};
+use crate::gfx::shadow::ShadowPass;
 use crate::scene::Transform;
 
+const INSTANCE_ATTRIBUTES: [wgpu::VertexAttribute; 4] =
+  wgpu::vertex_attr_array![3 => Float32x4, 4 => Float32x4, 5 => Float32x4, 6 => Float32x4];
+
 #[component]
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Model {
   pub mesh: Handle<Mesh>,
-  pub tex: Handle<Texture>,
+  pub material: Handle<Material>,
 }
 
-pub struct StandardPass(wgpu::RenderPipeline);
+pub struct StandardPass {
+  opaque: Rc<wgpu::RenderPipeline>,
+  mask: Rc<wgpu::RenderPipeline>,
+  blend: Rc<wgpu::RenderPipeline>,
+  instance_buf: wgpu::Buffer,
+  // blended models sorted back to front and their offset in the instance buffer
+  transparent: Vec<(Model, u64)>,
+}
 
 impl StandardPass {
   pub fn new(world: &World) -> Result<Self> {
@@ -26,98 +43,189 @@ impl StandardPass {
       .get_resource::<Assets>()
       .unwrap()
       .load::<Shader>("miau_shaders.spv")?;
-    let pipeline_layout = renderer
-      .device
-      .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        bind_group_layouts: &[&renderer.scene_layout, &renderer.tex_layout],
-        push_constant_ranges: &[wgpu::PushConstantRange {
-          stages: wgpu::ShaderStages::VERTEX,
-          range: 0..mem::size_of::<Mat4>() as _,
-        }],
-        label: None,
-      });
-    let pipeline = renderer
-      .device
-      .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-          module: &shader.0,
-          entry_point: "main_v",
-          buffers: &[wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<Vertex>() as _,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2=> Float32x3],
-          }],
-        },
-        fragment: Some(wgpu::FragmentState {
-          module: &shader.0,
-          entry_point: "main_f",
-          targets: &[Some(wgpu::ColorTargetState {
-            format: FORMAT,
-            blend: Some(wgpu::BlendState::REPLACE),
-            write_mask: wgpu::ColorWrites::ALL,
-          })],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: Some(wgpu::DepthStencilState {
-          format: DEPTH_FORMAT,
-          depth_write_enabled: true,
-          depth_compare: wgpu::CompareFunction::Less,
-          stencil: wgpu::StencilState::default(),
-          bias: wgpu::DepthBiasState::default(),
-        }),
-        multisample: wgpu::MultisampleState {
-          count: SAMPLES,
-          mask: !0,
-          alpha_to_coverage_enabled: false,
-        },
-        multiview: None,
-        label: None,
+    let [opaque, mask, blend] = Self::pipelines(world, &shader);
+    HotReload::watch(
+      world,
+      "miau_shaders.spv",
+      Self::pipelines,
+      |p: &mut Self, [opaque, mask, blend]| {
+        p.opaque = opaque;
+        p.mask = mask;
+        p.blend = blend;
+      },
+    );
+    let instance_buf = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+      size: 64 * mem::size_of::<Mat4>() as u64,
+      usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+      label: None,
+    });
+    let graph = world.get_resource_mut::<RenderGraph>().unwrap();
+    graph.add(
+      Pass::new("standard", Self::pass)
+        .read("shadow")
+        .write("color")
+        .write("depth"),
+    );
+    graph.add(
+      Pass::new("transparent", Self::transparent)
+        .read("shadow")
+        .read("depth")
+        .write("color")
+        .after("skybox"),
+    );
+    Ok(Self {
+      opaque,
+      mask,
+      blend,
+      instance_buf,
+      transparent: vec![],
+    })
+  }
+
+  // opaque, alpha mask and alpha blend
+  fn pipelines(world: &World, shader: &Shader) -> [Rc<wgpu::RenderPipeline>; 3] {
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    let builder = PipelineBuilder::new(shader, "main_v", "main_f")
+      .layout(SceneConst::get_layout(world))
+      .layout(&renderer.material_layout)
+      .layout(&renderer.light_layout)
+      .layout(&renderer.shadow_layout)
+      .buffer(wgpu::VertexBufferLayout {
+        array_stride: mem::size_of::<Mat4>() as _,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &INSTANCE_ATTRIBUTES,
       });
-    world.add_system(stage::DRAW, Self::pass);
-    Ok(Self(pipeline))
+    [
+      builder.build(world),
+      builder.clone().alpha_to_coverage(true).build(world),
+      builder
+        .blend(wgpu::BlendState::ALPHA_BLENDING)
+        .depth_write(false)
+        .build(world),
+    ]
   }
 
   fn pass(world: &World) -> Result {
     let renderer = world.get_resource::<Renderer>().unwrap();
     let frame = world.get_resource_mut::<Frame>().unwrap();
-    let pipeline = world.get_resource::<StandardPass>().unwrap();
+    let pass = world.get_resource_mut::<StandardPass>().unwrap();
+    let view = world.get_resource::<View>().unwrap();
+    let graph = world.get_resource::<RenderGraph>().unwrap();
     let models = world.get::<Model>();
+
+    let mut batches: Vec<(&Model, Vec<Mat4>)> = vec![];
+    let mut transparent = vec![];
+    let mut index = HashMap::new();
+    for (e, model) in &models {
+      let bounds = e.get_one::<Bounds>();
+      if !bounds
+        .as_deref()
+        .map_or(true, |b| view.frustum.intersects(b))
+      {
+        continue;
+      }
+      let Some(m) = Transform::model_matrix(world, e) else {
+        continue;
+      };
+      if model.material.desc.alpha_mode == AlphaMode::Blend {
+        let center = bounds.map_or(m.w_axis.truncate(), |b| b.sphere.center);
+        transparent.push((model, m, center.distance_squared(view.eye)));
+        continue;
+      }
+      let key = (
+        &*model.mesh as *const Mesh,
+        &*model.material as *const Material,
+      );
+      let i = *index.entry(key).or_insert_with(|| {
+        batches.push((model, vec![]));
+        batches.len() - 1
+      });
+      batches[i].1.push(m);
+    }
+    transparent.sort_by(|a, b| b.2.total_cmp(&a.2));
+    let transforms: Vec<Mat4> = batches
+      .iter()
+      .flat_map(|(_, t)| t.iter().copied())
+      .chain(transparent.iter().map(|(_, m, _)| *m))
+      .collect();
+    renderer.write_growing(&mut pass.instance_buf, cast_slice(&transforms));
+    let opaque = batches.iter().map(|(_, t)| t.len()).sum::<usize>();
+    pass.transparent = transparent
+      .iter()
+      .enumerate()
+      .map(|(i, (model, _, _))| {
+        (
+          Model::clone(model),
+          ((opaque + i) * mem::size_of::<Mat4>()) as u64,
+        )
+      })
+      .collect();
+
+    let mut render_pass = frame
+      .encoder
+      .begin_render_pass(&wgpu::RenderPassDescriptor {
+        color_attachments: &[Some(graph.color_attachment("color"))],
+        depth_stencil_attachment: Some(graph.depth_attachment("depth")),
+        occlusion_query_set: None,
+        timestamp_writes: None,
+        label: None,
+      });
+    Self::bind(world, &mut render_pass);
+    let mut offset = 0;
+    for (model, transforms) in &batches {
+      render_pass.set_pipeline(match model.material.desc.alpha_mode {
+        AlphaMode::Mask(_) => &pass.mask,
+        _ => &pass.opaque,
+      });
+      render_pass.set_vertex_buffer(1, pass.instance_buf.slice(offset..));
+      model.material.bind(&mut render_pass, 1);
+      model.mesh.render(&mut render_pass, transforms.len() as _);
+      offset += mem::size_of_val(transforms.as_slice()) as u64;
+    }
+    Ok(())
+  }
+
+  // after the skybox so blended models composite over it, without writing depth
+  fn transparent(world: &World) -> Result {
+    let frame = world.get_resource_mut::<Frame>().unwrap();
+    let pass = world.get_resource::<StandardPass>().unwrap();
+    let graph = world.get_resource::<RenderGraph>().unwrap();
+    if pass.transparent.is_empty() {
+      return Ok(());
+    }
+
     let mut render_pass = frame
       .encoder
       .begin_render_pass(&wgpu::RenderPassDescriptor {
-        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-          view: &renderer.textures.fb,
-          resolve_target: Some(&frame.surface_view),
-          ops: wgpu::Operations {
-            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-            store: wgpu::StoreOp::Store,
-          },
-        })],
-        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-          view: &renderer.textures.depth,
-          depth_ops: Some(wgpu::Operations {
-            load: wgpu::LoadOp::Clear(1.0),
-            store: wgpu::StoreOp::Store,
-          }),
-          stencil_ops: None,
-        }),
+        color_attachments: &[Some(graph.color_attachment("color"))],
+        depth_stencil_attachment: Some(graph.depth_attachment("depth")),
         occlusion_query_set: None,
         timestamp_writes: None,
         label: None,
       });
-    render_pass.set_pipeline(&pipeline.0);
+    render_pass.set_pipeline(&pass.blend);
+    Self::bind(world, &mut render_pass);
+    for (model, offset) in &pass.transparent {
+      render_pass.set_vertex_buffer(1, pass.instance_buf.slice(*offset..));
+      model.material.bind(&mut render_pass, 1);
+      model.mesh.render(&mut render_pass, 1);
+    }
+    Ok(())
+  }
+
+  fn bind<'a>(world: &'a World, render_pass: &mut wgpu::RenderPass<'a>) {
     world
       .get_resource::<Binding<SceneConst>>()
       .unwrap()
-      .bind(&mut render_pass, 0);
-    for (e, model) in &models {
-      if let Some(t) = e.get_one_mut::<Transform>() {
-        render_pass.set_push_constants(wgpu::ShaderStages::VERTEX, 0, cast(&t.as_mat4()));
-        model.tex.bind(&mut render_pass, 1);
-        model.mesh.render(&mut render_pass, 1);
-      }
-    }
-    Ok(())
+      .bind(render_pass, 0);
+    world
+      .get_resource::<Binding<Lights>>()
+      .unwrap()
+      .bind(render_pass, 2);
+    world
+      .get_resource::<ShadowPass>()
+      .unwrap()
+      .bind(render_pass, 3);
   }
 }
@@ -0,0 +1,625 @@
+use wgpu::TextureFormat as F;
+use std::f32::consts::{PI, TAU};
+use glam::Vec3;
+use image::{DynamicImage, ImageBuffer, Pixel, Rgba, RgbaImage, Rgba32FImage, imageops};
+use ktx2::Format as KtxFormat;
+use ddsfile::{Dds, DxgiFormat, D3DFormat, Caps2, MiscFlag};
+use half::f16;
+use log::warn;
+use crate::Result;
+use crate::gfx::TextureSettings;
+
+const KTX2_MAGIC: &[u8] = b"\xabKTX 20\xbb\r\n\x1a\n";
+const DDS_MAGIC: &[u8] = b"DDS ";
+
+type BlockDecoder = fn(&[u8], &mut [u32]);
+
+pub struct TextureData {
+  pub format: wgpu::TextureFormat,
+  pub width: u32,
+  pub height: u32,
+  pub layers: u32,
+  pub levels: Vec<Vec<u8>>,
+}
+
+impl TextureData {
+  pub fn decode(data: &[u8], settings: &TextureSettings) -> Result<Self> {
+    let tex = match data {
+      d if d.starts_with(KTX2_MAGIC) => Self::ktx2(d)?,
+      d if d.starts_with(DDS_MAGIC) => Self::dds(d, settings)?,
+      d => return Ok(Self::from_image(&image::load_from_memory(d)?, settings)),
+    };
+    Ok(match tex.format {
+      F::Rgba32Float => tex.into_half(),
+      _ => tex,
+    })
+  }
+
+  pub fn from_image(img: &DynamicImage, settings: &TextureSettings) -> Self {
+    let levels = match settings.mipmaps {
+      true => 32 - img.width().max(img.height()).leading_zeros(),
+      false => 1,
+    };
+    match img {
+      DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => Self {
+        format: F::Rgba16Float,
+        width: img.width(),
+        height: img.height(),
+        layers: 1,
+        levels: mips(img.to_rgba32f(), levels)
+          .iter()
+          .map(|l| {
+            l.iter()
+              .flat_map(|c| f16::from_f32(*c).to_le_bytes())
+              .collect()
+          })
+          .collect(),
+      },
+      _ => Self {
+        format: match settings.srgb {
+          true => F::Rgba8UnormSrgb,
+          false => F::Rgba8Unorm,
+        },
+        width: img.width(),
+        height: img.height(),
+        layers: 1,
+        levels: match settings.srgb {
+          true => srgb_mips(img.to_rgba8(), levels),
+          false => mips(img.to_rgba8(), levels)
+            .into_iter()
+            .map(|l| l.into_raw())
+            .collect(),
+        },
+      },
+    }
+  }
+
+  pub fn is_container(data: &[u8]) -> bool {
+    data.starts_with(KTX2_MAGIC) || data.starts_with(DDS_MAGIC)
+  }
+
+  pub fn faces(faces: &[DynamicImage], settings: &TextureSettings) -> Result<Self> {
+    let faces: Vec<_> = faces
+      .iter()
+      .map(|f| {
+        Self::from_image(
+          f,
+          &TextureSettings {
+            mipmaps: false,
+            ..*settings
+          },
+        )
+      })
+      .collect();
+    let first = faces.first().ok_or("cubemap has no faces")?;
+    if faces.len() != 6
+      || faces
+        .iter()
+        .any(|f| (f.width, f.height, f.format) != (first.width, first.height, first.format))
+    {
+      return Err("cubemap needs 6 faces of the same size and format".into());
+    }
+    Ok(Self {
+      format: first.format,
+      width: first.width,
+      height: first.height,
+      layers: 6,
+      levels: vec![faces
+        .iter()
+        .flat_map(|f| f.levels[0].iter().copied())
+        .collect()],
+    })
+  }
+
+  pub fn equirect(img: &DynamicImage, settings: &TextureSettings) -> Self {
+    let hdr = matches!(
+      img,
+      DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+    let src = img.to_rgba32f();
+    let size = (src.height() / 2).max(1);
+    let mut levels = vec![vec![]];
+    for face in 0..6 {
+      for y in 0..size {
+        for x in 0..size {
+          let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+          let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+          let dir = match face {
+            0 => Vec3::new(1.0, -v, -u),
+            1 => Vec3::new(-1.0, -v, u),
+            2 => Vec3::new(u, 1.0, v),
+            3 => Vec3::new(u, -1.0, -v),
+            4 => Vec3::new(u, -v, 1.0),
+            _ => Vec3::new(-u, -v, -1.0),
+          }
+          .normalize();
+          let c = imageops::sample_bilinear(
+            &src,
+            0.5 + dir.z.atan2(dir.x) / TAU,
+            dir.y.clamp(-1.0, 1.0).acos() / PI,
+          )
+          .unwrap_or(Rgba([0.0; 4]));
+          match hdr {
+            true => levels[0].extend(c.0.iter().flat_map(|c| f16::from_f32(*c).to_le_bytes())),
+            false => levels[0].extend(
+              c.0
+                .iter()
+                .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8),
+            ),
+          }
+        }
+      }
+    }
+    Self {
+      format: match (hdr, settings.srgb) {
+        (true, _) => F::Rgba16Float,
+        (false, true) => F::Rgba8UnormSrgb,
+        (false, false) => F::Rgba8Unorm,
+      },
+      width: size,
+      height: size,
+      layers: 6,
+      levels,
+    }
+  }
+
+  fn ktx2(data: &[u8]) -> Result<Self> {
+    let reader = ktx2::Reader::new(data).map_err(|e| format!("{:?}", e))?;
+    let header = reader.header();
+    if header.supercompression_scheme.is_some() {
+      return Err("supercompressed KTX2 files are not supported".into());
+    }
+    if header.layer_count > 1 || header.pixel_depth > 1 {
+      return Err("only 2D and cubemap KTX2 textures are supported".into());
+    }
+    let format = match header.format.ok_or("KTX2 file has no format")? {
+      KtxFormat::R8G8B8A8_UNORM => F::Rgba8Unorm,
+      KtxFormat::R8G8B8A8_SRGB => F::Rgba8UnormSrgb,
+      KtxFormat::R16G16B16A16_SFLOAT => F::Rgba16Float,
+      KtxFormat::R32G32B32A32_SFLOAT => F::Rgba32Float,
+      KtxFormat::BC1_RGB_UNORM_BLOCK | KtxFormat::BC1_RGBA_UNORM_BLOCK => F::Bc1RgbaUnorm,
+      KtxFormat::BC1_RGB_SRGB_BLOCK | KtxFormat::BC1_RGBA_SRGB_BLOCK => F::Bc1RgbaUnormSrgb,
+      KtxFormat::BC2_UNORM_BLOCK => F::Bc2RgbaUnorm,
+      KtxFormat::BC2_SRGB_BLOCK => F::Bc2RgbaUnormSrgb,
+      KtxFormat::BC3_UNORM_BLOCK => F::Bc3RgbaUnorm,
+      KtxFormat::BC3_SRGB_BLOCK => F::Bc3RgbaUnormSrgb,
+      KtxFormat::BC4_UNORM_BLOCK => F::Bc4RUnorm,
+      KtxFormat::BC4_SNORM_BLOCK => F::Bc4RSnorm,
+      KtxFormat::BC5_UNORM_BLOCK => F::Bc5RgUnorm,
+      KtxFormat::BC5_SNORM_BLOCK => F::Bc5RgSnorm,
+      KtxFormat::BC6H_UFLOAT_BLOCK => F::Bc6hRgbUfloat,
+      KtxFormat::BC6H_SFLOAT_BLOCK => F::Bc6hRgbFloat,
+      KtxFormat::BC7_UNORM_BLOCK => F::Bc7RgbaUnorm,
+      KtxFormat::BC7_SRGB_BLOCK => F::Bc7RgbaUnormSrgb,
+      f => return Err(format!("unsupported KTX2 format {:?}", f).into()),
+    };
+    Ok(Self {
+      format,
+      width: header.pixel_width,
+      height: header.pixel_height.max(1),
+      layers: header.face_count,
+      levels: reader.levels().map(|l| l.to_vec()).collect(),
+    })
+  }
+
+  fn dds(data: &[u8], settings: &TextureSettings) -> Result<Self> {
+    let dds = Dds::read(data)?;
+    let format = match (dds.get_dxgi_format(), dds.get_d3d_format()) {
+      (Some(f), _) => match f {
+        DxgiFormat::R8G8B8A8_UNorm => F::Rgba8Unorm,
+        DxgiFormat::R8G8B8A8_UNorm_sRGB => F::Rgba8UnormSrgb,
+        DxgiFormat::R16G16B16A16_Float => F::Rgba16Float,
+        DxgiFormat::R32G32B32A32_Float => F::Rgba32Float,
+        DxgiFormat::BC1_UNorm => F::Bc1RgbaUnorm,
+        DxgiFormat::BC1_UNorm_sRGB => F::Bc1RgbaUnormSrgb,
+        DxgiFormat::BC2_UNorm => F::Bc2RgbaUnorm,
+        DxgiFormat::BC2_UNorm_sRGB => F::Bc2RgbaUnormSrgb,
+        DxgiFormat::BC3_UNorm => F::Bc3RgbaUnorm,
+        DxgiFormat::BC3_UNorm_sRGB => F::Bc3RgbaUnormSrgb,
+        DxgiFormat::BC4_UNorm => F::Bc4RUnorm,
+        DxgiFormat::BC4_SNorm => F::Bc4RSnorm,
+        DxgiFormat::BC5_UNorm => F::Bc5RgUnorm,
+        DxgiFormat::BC5_SNorm => F::Bc5RgSnorm,
+        DxgiFormat::BC6H_UF16 => F::Bc6hRgbUfloat,
+        DxgiFormat::BC6H_SF16 => F::Bc6hRgbFloat,
+        DxgiFormat::BC7_UNorm => F::Bc7RgbaUnorm,
+        DxgiFormat::BC7_UNorm_sRGB => F::Bc7RgbaUnormSrgb,
+        f => return Err(format!("unsupported DDS format {:?}", f).into()),
+      },
+      (None, Some(f)) => match (f, settings.srgb) {
+        (D3DFormat::A8B8G8R8, false) => F::Rgba8Unorm,
+        (D3DFormat::A8B8G8R8, true) => F::Rgba8UnormSrgb,
+        (D3DFormat::A16B16G16R16F, _) => F::Rgba16Float,
+        (D3DFormat::A32B32G32R32F, _) => F::Rgba32Float,
+        (D3DFormat::DXT1, false) => F::Bc1RgbaUnorm,
+        (D3DFormat::DXT1, true) => F::Bc1RgbaUnormSrgb,
+        (D3DFormat::DXT3, false) => F::Bc2RgbaUnorm,
+        (D3DFormat::DXT3, true) => F::Bc2RgbaUnormSrgb,
+        (D3DFormat::DXT5, false) => F::Bc3RgbaUnorm,
+        (D3DFormat::DXT5, true) => F::Bc3RgbaUnormSrgb,
+        (f, _) => return Err(format!("unsupported DDS format {:?}", f).into()),
+      },
+      (None, None) => return Err("DDS file has no known format".into()),
+    };
+    let (width, height) = (dds.get_width(), dds.get_height());
+    let cube = dds.header.caps2.contains(Caps2::CUBEMAP)
+      || dds
+        .header10
+        .as_ref()
+        .is_some_and(|h| h.misc_flag.contains(MiscFlag::TEXTURECUBE));
+    let layers = if cube { 6 } else { 1 };
+    let sizes: Vec<usize> = (0..dds.get_num_mipmap_levels().max(1))
+      .map(|i| level_size(format, (width >> i).max(1), (height >> i).max(1)))
+      .collect();
+    let chain = sizes.iter().sum::<usize>();
+    if dds.data.len() < chain * layers as usize {
+      return Err("DDS file is truncated".into());
+    }
+    let mut levels = vec![vec![]; sizes.len()];
+    for face in dds.data.chunks_exact(chain).take(layers as _) {
+      let mut offset = 0;
+      for (level, size) in levels.iter_mut().zip(&sizes) {
+        level.extend_from_slice(&face[offset..offset + size]);
+        offset += size;
+      }
+    }
+    Ok(Self {
+      format,
+      width,
+      height,
+      layers,
+      levels,
+    })
+  }
+
+  fn into_half(self) -> Self {
+    Self {
+      format: F::Rgba16Float,
+      levels: self
+        .levels
+        .iter()
+        .map(|l| {
+          l.chunks_exact(4)
+            .flat_map(|c| f16::from_f32(f32::from_le_bytes(c.try_into().unwrap())).to_le_bytes())
+            .collect()
+        })
+        .collect(),
+      ..self
+    }
+  }
+
+  pub fn supported(self, device: &wgpu::Device) -> Self {
+    match self.format.is_compressed()
+      && !device
+        .features()
+        .contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+    {
+      true => self.decompress(),
+      false => self,
+    }
+  }
+
+  pub fn decompress(self) -> Self {
+    let (block, decode): (usize, BlockDecoder) = match self.format {
+      F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb => (8, texture2ddecoder::decode_bc1_block),
+      F::Bc2RgbaUnorm | F::Bc2RgbaUnormSrgb => (16, decode_bc2_block),
+      F::Bc3RgbaUnorm | F::Bc3RgbaUnormSrgb => (16, decode_bc3_block),
+      F::Bc4RUnorm => (8, texture2ddecoder::decode_bc4_block),
+      F::Bc4RSnorm => (8, decode_bc4_snorm_block),
+      F::Bc5RgUnorm => (16, texture2ddecoder::decode_bc5_block),
+      F::Bc5RgSnorm => (16, decode_bc5_snorm_block),
+      F::Bc6hRgbUfloat => (16, texture2ddecoder::decode_bc6_block_unsigned),
+      F::Bc6hRgbFloat => (16, texture2ddecoder::decode_bc6_block_signed),
+      F::Bc7RgbaUnorm | F::Bc7RgbaUnormSrgb => (16, texture2ddecoder::decode_bc7_block),
+      _ => return self,
+    };
+    if matches!(self.format, F::Bc6hRgbUfloat | F::Bc6hRgbFloat) {
+      warn!("Decoding BC6H on the CPU, HDR range will be lost.");
+    }
+    let levels = self
+      .levels
+      .iter()
+      .enumerate()
+      .map(|(i, data)| {
+        let (w, h) = (
+          (self.width as usize >> i).max(1),
+          (self.height as usize >> i).max(1),
+        );
+        let (bw, bh) = ((w + 3) / 4, (h + 3) / 4);
+        let mut out = vec![0; w * h * 4 * self.layers as usize];
+        let mut buf = [0u32; 16];
+        for (n, b) in data.chunks_exact(block).enumerate() {
+          decode(b, &mut buf);
+          let (layer, n) = (n / (bw * bh), n % (bw * bh));
+          let (bx, by) = (n % bw * 4, n / bw * 4);
+          for (p, c) in buf.iter().enumerate() {
+            let (x, y) = (bx + p % 4, by + p / 4);
+            if x < w && y < h {
+              let [b, g, r, a] = c.to_le_bytes();
+              out[((layer * h + y) * w + x) * 4..][..4].copy_from_slice(&[r, g, b, a]);
+            }
+          }
+        }
+        out
+      })
+      .collect();
+    Self {
+      format: match self.format {
+        F::Bc4RSnorm | F::Bc5RgSnorm => F::Rgba8Snorm,
+        f if f.is_srgb() => F::Rgba8UnormSrgb,
+        _ => F::Rgba8Unorm,
+      },
+      levels,
+      ..self
+    }
+  }
+}
+
+fn mips<P: Pixel + 'static>(
+  img: ImageBuffer<P, Vec<P::Subpixel>>,
+  levels: u32,
+) -> Vec<ImageBuffer<P, Vec<P::Subpixel>>> {
+  let mut mips = vec![img];
+  for _ in 1..levels {
+    let last = mips.last().unwrap();
+    let next = imageops::resize(
+      last,
+      (last.width() / 2).max(1),
+      (last.height() / 2).max(1),
+      imageops::FilterType::Triangle,
+    );
+    mips.push(next);
+  }
+  mips
+}
+
+// filters in linear space so the smaller levels don't get darker
+fn srgb_mips(img: RgbaImage, levels: u32) -> Vec<Vec<u8>> {
+  let to_linear: Vec<f32> = (0..=255)
+    .map(|c| {
+      let c = c as f32 / 255.0;
+      match c <= 0.04045 {
+        true => c / 12.92,
+        false => ((c + 0.055) / 1.055).powf(2.4),
+      }
+    })
+    .collect();
+  let to_srgb = |c: f32| {
+    let c = c.clamp(0.0, 1.0);
+    let c = match c <= 0.0031308 {
+      true => c * 12.92,
+      false => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+    };
+    (c * 255.0).round() as u8
+  };
+  let linear = Rgba32FImage::from_fn(img.width(), img.height(), |x, y| {
+    let [r, g, b, a] = img.get_pixel(x, y).0;
+    Rgba([
+      to_linear[r as usize],
+      to_linear[g as usize],
+      to_linear[b as usize],
+      a as f32 / 255.0,
+    ])
+  });
+  let mut levels: Vec<_> = mips(linear, levels)
+    .into_iter()
+    .map(|l| {
+      l.pixels()
+        .flat_map(|p| {
+          let [r, g, b, a] = p.0;
+          [
+            to_srgb(r),
+            to_srgb(g),
+            to_srgb(b),
+            (a.clamp(0.0, 1.0) * 255.0).round() as u8,
+          ]
+        })
+        .collect()
+    })
+    .collect();
+  levels[0] = img.into_raw();
+  levels
+}
+
+pub fn write_level(queue: &wgpu::Queue, texture: &wgpu::Texture, level: u32, data: &[u8]) {
+  let format = texture.format();
+  let size = texture
+    .size()
+    .mip_level_size(level, texture.dimension())
+    .physical_size(format);
+  let (bw, bh) = format.block_dimensions();
+  queue.write_texture(
+    wgpu::ImageCopyTexture {
+      texture,
+      mip_level: level,
+      origin: wgpu::Origin3d::ZERO,
+      aspect: wgpu::TextureAspect::All,
+    },
+    data,
+    wgpu::ImageDataLayout {
+      offset: 0,
+      bytes_per_row: Some(size.width / bw * format.block_size(None).unwrap()),
+      rows_per_image: Some(size.height / bh),
+    },
+    size,
+  );
+}
+
+pub fn level_size(format: wgpu::TextureFormat, width: u32, height: u32) -> usize {
+  let (bw, bh) = format.block_dimensions();
+  let block = format.block_size(None).unwrap();
+  ((width + bw - 1) / bw * ((height + bh - 1) / bh) * block) as usize
+}
+
+// bc2 and bc3 color blocks always have four colors, bc1 switches to three and
+// transparent black when c0 <= c1
+fn decode_color_block(data: &[u8], out: &mut [u32]) {
+  let rgb = |c: u16| {
+    let (r, g, b) = ((c >> 11) & 31, (c >> 5) & 63, c & 31);
+    [
+      (r << 3 | r >> 2) as u32,
+      (g << 2 | g >> 4) as u32,
+      (b << 3 | b >> 2) as u32,
+    ]
+  };
+  let c0 = rgb(u16::from_le_bytes([data[0], data[1]]));
+  let c1 = rgb(u16::from_le_bytes([data[2], data[3]]));
+  let mix = |a: [u32; 3], b: [u32; 3]| [0, 1, 2].map(|i| (a[i] * 2 + b[i]) / 3);
+  let colors = [c0, c1, mix(c0, c1), mix(c1, c0)]
+    .map(|[r, g, b]| u32::from_le_bytes([b as u8, g as u8, r as u8, 255]));
+  let indices = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+  for (i, c) in out.iter_mut().enumerate().take(16) {
+    *c = colors[(indices >> (i * 2)) as usize & 3];
+  }
+}
+
+fn decode_bc2_block(data: &[u8], out: &mut [u32]) {
+  decode_color_block(&data[8..], out);
+  for (i, c) in out.iter_mut().enumerate().take(16) {
+    let a = (data[i / 2] >> (i % 2 * 4)) & 0xf;
+    *c = (*c & 0x00ff_ffff) | ((a as u32 * 17) << 24);
+  }
+}
+
+fn decode_bc3_block(data: &[u8], out: &mut [u32]) {
+  texture2ddecoder::decode_bc3_block(data, out);
+  let mut colors = [0; 16];
+  decode_color_block(&data[8..], &mut colors);
+  for (c, rgb) in out.iter_mut().zip(colors) {
+    *c = (*c & 0xff00_0000) | (rgb & 0x00ff_ffff);
+  }
+}
+
+// the signed bc4 channel, endpoints and the extra values are in -127..=127
+fn decode_snorm_channel(data: &[u8]) -> [i8; 16] {
+  let (c0, c1) = (
+    (data[0] as i8).max(-127) as i32,
+    (data[1] as i8).max(-127) as i32,
+  );
+  let mut values = [c0, c1, 0, 0, 0, 0, -127, 127];
+  match c0 > c1 {
+    true => (1..7).for_each(|i| values[i as usize + 1] = ((7 - i) * c0 + i * c1) / 7),
+    false => (1..5).for_each(|i| values[i as usize + 1] = ((5 - i) * c0 + i * c1) / 5),
+  }
+  let mut bits = [0; 8];
+  bits[..6].copy_from_slice(&data[2..8]);
+  let indices = u64::from_le_bytes(bits);
+  std::array::from_fn(|i| values[(indices >> (i * 3)) as usize & 7] as i8)
+}
+
+fn decode_bc4_snorm_block(data: &[u8], out: &mut [u32]) {
+  for (c, r) in out.iter_mut().zip(decode_snorm_channel(data)) {
+    *c = u32::from_le_bytes([0, 0, r as u8, 127]);
+  }
+}
+
+fn decode_bc5_snorm_block(data: &[u8], out: &mut [u32]) {
+  let (r, g) = (
+    decode_snorm_channel(&data[..8]),
+    decode_snorm_channel(&data[8..]),
+  );
+  for (i, c) in out.iter_mut().enumerate().take(16) {
+    *c = u32::from_le_bytes([0, g[i] as u8, r[i] as u8, 127]);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rgba(c: u32) -> [u8; 4] {
+    let [b, g, r, a] = c.to_le_bytes();
+    [r, g, b, a]
+  }
+
+  // black and white endpoints with c0 < c1, pixels 0 to 3 use indices 0 to 3
+  const COLOR: [u8; 8] = [0x00, 0x00, 0xff, 0xff, 0xe4, 0xe4, 0xe4, 0xe4];
+
+  #[test]
+  fn bc2_four_colors() {
+    let mut block = [0; 16];
+    for (i, a) in block[..8].iter_mut().enumerate() {
+      *a = (i as u8 * 2) | (i as u8 * 2 + 1) << 4;
+    }
+    block[8..].copy_from_slice(&COLOR);
+    let mut out = [0; 16];
+    decode_bc2_block(&block, &mut out);
+    assert_eq!(rgba(out[0]), [0, 0, 0, 0]);
+    assert_eq!(rgba(out[1]), [255, 255, 255, 17]);
+    assert_eq!(rgba(out[2]), [85, 85, 85, 34]);
+    assert_eq!(rgba(out[3]), [170, 170, 170, 51]);
+    assert_eq!(rgba(out[15]), [170, 170, 170, 255]);
+  }
+
+  #[test]
+  fn bc3_four_colors() {
+    // alpha endpoints 255 and 0, every index 0
+    let mut block = [0; 16];
+    block[0] = 255;
+    block[8..].copy_from_slice(&COLOR);
+    let mut out = [0; 16];
+    decode_bc3_block(&block, &mut out);
+    assert_eq!(rgba(out[2]), [85, 85, 85, 255]);
+    assert_eq!(rgba(out[3]), [170, 170, 170, 255]);
+  }
+
+  fn decompress(format: wgpu::TextureFormat, block: &[u8]) -> TextureData {
+    TextureData {
+      format,
+      width: 4,
+      height: 4,
+      layers: 1,
+      levels: vec![block.to_vec()],
+    }
+    .decompress()
+  }
+
+  #[test]
+  fn bc1_three_colors() {
+    let data = decompress(F::Bc1RgbaUnormSrgb, &COLOR);
+    assert_eq!(data.format, F::Rgba8UnormSrgb);
+    assert_eq!(data.levels[0][8..16], [127, 127, 127, 255, 0, 0, 0, 255]);
+    assert_eq!(decompress(F::Bc1RgbaUnorm, &COLOR).format, F::Rgba8Unorm);
+  }
+
+  // endpoints 64 and -64 with c0 > c1, pixels 0 to 2 use indices 0 to 2
+  const SIGNED: [u8; 8] = [0x40, 0xc0, 0x88, 0, 0, 0, 0, 0];
+
+  #[test]
+  fn bc4_signed() {
+    let data = decompress(F::Bc4RSnorm, &SIGNED);
+    assert_eq!(data.format, F::Rgba8Snorm);
+    let red: Vec<i8> = data.levels[0].chunks(4).map(|p| p[0] as i8).collect();
+    assert_eq!(red[..3], [64, -64, 45]);
+    assert_eq!(data.levels[0][1..4], [0, 0, 127]);
+    // as unorm the same bytes are 64 and 192
+    let data = decompress(F::Bc4RUnorm, &SIGNED);
+    assert_eq!(data.format, F::Rgba8Unorm);
+    assert_eq!([data.levels[0][0], data.levels[0][4]], [64, 192]);
+  }
+
+  #[test]
+  fn bc5_signed() {
+    // -128 clamps to -127, c0 <= c1 so index 6 and 7 are the extremes
+    let green = [0x80, 0x7f, 0xbe, 0x01, 0, 0, 0, 0];
+    let data = decompress(F::Bc5RgSnorm, &[SIGNED, green].concat());
+    assert_eq!(data.format, F::Rgba8Snorm);
+    let rg: Vec<[i8; 2]> = data.levels[0]
+      .chunks(4)
+      .map(|p| [p[0] as i8, p[1] as i8])
+      .collect();
+    assert_eq!(rg[..3], [[64, -127], [-64, 127], [45, -127]]);
+  }
+
+  #[test]
+  fn srgb_mips_average_in_linear() {
+    // black and white checker, averages to half the light which is 188 in srgb, not 128
+    let img = RgbaImage::from_fn(2, 2, |x, y| match (x + y) % 2 {
+      0 => Rgba([0, 0, 0, 255]),
+      _ => Rgba([255, 255, 255, 255]),
+    });
+    let levels = srgb_mips(img.clone(), 2);
+    assert_eq!(levels[0], img.into_raw());
+    assert_eq!(levels[1], [188, 188, 188, 255]);
+  }
+}
@@ -0,0 +1,273 @@
+pub mod record;
+
+use std::collections::{HashMap, HashSet};
+use winit::event::{WindowEvent, ElementState, MouseScrollDelta};
+use winit::keyboard::PhysicalKey;
+use glam::Vec2;
+use serde::{Serialize, Deserialize};
+use crate::Result;
+use crate::ecs::{World, stage};
+use crate::assets::asset;
+use record::Recorder;
+
+pub use winit::keyboard::KeyCode;
+pub use winit::event::MouseButton;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Button {
+  Key(KeyCode),
+  Mouse(MouseButton),
+}
+
+impl From<KeyCode> for Button {
+  fn from(k: KeyCode) -> Self {
+    Self::Key(k)
+  }
+}
+
+impl From<MouseButton> for Button {
+  fn from(b: MouseButton) -> Self {
+    Self::Mouse(b)
+  }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum InputEvent {
+  Button(Button, bool),
+  CursorMoved(Vec2),
+  MouseMotion(Vec2),
+  Scroll(Vec2),
+}
+
+pub struct Input {
+  pressed: HashSet<Button>,
+  just_pressed: HashSet<Button>,
+  just_released: HashSet<Button>,
+  cursor: Vec2,
+  cursor_delta: Vec2,
+  motion: Vec2,
+  scroll: Vec2,
+  actions: HashMap<String, Vec<Button>>,
+}
+
+impl Input {
+  pub fn init(world: &World) -> Result {
+    world.add_resource(Self {
+      pressed: HashSet::new(),
+      just_pressed: HashSet::new(),
+      just_released: HashSet::new(),
+      cursor: Vec2::ZERO,
+      cursor_delta: Vec2::ZERO,
+      motion: Vec2::ZERO,
+      scroll: Vec2::ZERO,
+      actions: HashMap::new(),
+    });
+    world.add_system(stage::EVENT, Self::event);
+    if world.get_resource::<Recorder>().is_some() {
+      world.add_system(stage::PRE_UPDATE, Recorder::pre);
+      world.add_system(stage::POST_DRAW, Recorder::post);
+    }
+    Ok(())
+  }
+
+  fn event(world: &World) -> Result {
+    match world.get_resource::<WindowEvent>().unwrap() {
+      WindowEvent::KeyboardInput { event, .. } => {
+        if let PhysicalKey::Code(k) = event.physical_key {
+          if !event.repeat {
+            Self::send(
+              world,
+              InputEvent::Button(k.into(), event.state == ElementState::Pressed),
+            );
+          }
+        }
+      }
+      WindowEvent::MouseInput { state, button, .. } => {
+        Self::send(
+          world,
+          InputEvent::Button((*button).into(), *state == ElementState::Pressed),
+        );
+      }
+      WindowEvent::CursorMoved { position, .. } => {
+        Self::send(
+          world,
+          InputEvent::CursorMoved(Vec2::new(position.x as _, position.y as _)),
+        );
+      }
+      WindowEvent::MouseWheel { delta, .. } => {
+        Self::send(
+          world,
+          InputEvent::Scroll(match delta {
+            MouseScrollDelta::LineDelta(h, v) => Vec2::new(*h, *v),
+            MouseScrollDelta::PixelDelta(pos) => {
+              Vec2::new(0.01 * pos.x as f32, 0.01 * pos.y as f32)
+            }
+          }),
+        );
+      }
+      WindowEvent::Focused(false) => {
+        let input = world.get_resource::<Input>().unwrap();
+        for b in input.pressed.clone() {
+          Self::send(world, InputEvent::Button(b, false));
+        }
+      }
+      _ => {}
+    }
+    Ok(())
+  }
+
+  pub(crate) fn send(world: &World, event: InputEvent) {
+    if let Some(recorder) = world.get_resource_mut::<Recorder>() {
+      if recorder.replaying() {
+        return;
+      }
+      recorder.push(event);
+    }
+    world.get_resource_mut::<Input>().unwrap().handle(event);
+  }
+
+  pub fn handle(&mut self, event: InputEvent) {
+    match event {
+      InputEvent::Button(b, true) => {
+        if self.pressed.insert(b) {
+          self.just_pressed.insert(b);
+        }
+      }
+      InputEvent::Button(b, false) => {
+        if self.pressed.remove(&b) {
+          self.just_released.insert(b);
+        }
+      }
+      InputEvent::CursorMoved(pos) => {
+        self.cursor_delta += pos - self.cursor;
+        self.cursor = pos;
+      }
+      InputEvent::MouseMotion(delta) => self.motion += delta,
+      InputEvent::Scroll(delta) => self.scroll += delta,
+    }
+  }
+
+  pub(crate) fn clear(&mut self) {
+    self.just_pressed.clear();
+    self.just_released.clear();
+    self.cursor_delta = Vec2::ZERO;
+    self.motion = Vec2::ZERO;
+    self.scroll = Vec2::ZERO;
+  }
+
+  pub fn pressed<B: Into<Button>>(&self, b: B) -> bool {
+    self.pressed.contains(&b.into())
+  }
+
+  pub fn just_pressed<B: Into<Button>>(&self, b: B) -> bool {
+    self.just_pressed.contains(&b.into())
+  }
+
+  pub fn just_released<B: Into<Button>>(&self, b: B) -> bool {
+    self.just_released.contains(&b.into())
+  }
+
+  pub fn cursor(&self) -> Vec2 {
+    self.cursor
+  }
+
+  pub fn cursor_delta(&self) -> Vec2 {
+    self.cursor_delta
+  }
+
+  pub fn mouse_motion(&self) -> Vec2 {
+    self.motion
+  }
+
+  pub fn scroll(&self) -> Vec2 {
+    self.scroll
+  }
+
+  pub fn bind<B: Into<Button>>(&mut self, action: &str, b: B) {
+    self
+      .actions
+      .entry(action.to_string())
+      .or_insert(vec![])
+      .push(b.into());
+  }
+
+  pub fn set_actions(&mut self, actions: &Actions) {
+    self.actions = actions.0.clone();
+  }
+
+  pub fn action(&self, action: &str) -> bool {
+    self.any(action, &self.pressed)
+  }
+
+  pub fn action_just_pressed(&self, action: &str) -> bool {
+    self.any(action, &self.just_pressed)
+  }
+
+  pub fn action_just_released(&self, action: &str) -> bool {
+    self.any(action, &self.just_released)
+  }
+
+  fn any(&self, action: &str, set: &HashSet<Button>) -> bool {
+    self
+      .actions
+      .get(action)
+      .map_or(false, |v| v.iter().any(|b| set.contains(b)))
+  }
+}
+
+#[asset(Actions::load)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Actions(pub HashMap<String, Vec<Button>>);
+
+impl Actions {
+  fn load(data: &[u8]) -> Result<Self> {
+    Ok(serde_json::from_slice(data)?)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn world() -> World {
+    let world = World::new();
+    Input::init(&world).unwrap();
+    world
+  }
+
+  #[test]
+  fn actions() {
+    let world = world();
+    let input = world.get_resource_mut::<Input>().unwrap();
+    let actions = br#"{ "jump": [{ "Key": "Space" }, { "Mouse": "Left" }] }"#;
+    input.set_actions(&Actions::load(actions).unwrap());
+    input.bind("fire", KeyCode::KeyF);
+
+    input.handle(InputEvent::Button(MouseButton::Left.into(), true));
+    assert!(input.action("jump") && input.action_just_pressed("jump"));
+    assert!(!input.action("fire") && !input.action("missing"));
+    input.clear();
+    assert!(input.action("jump") && !input.action_just_pressed("jump"));
+    input.handle(InputEvent::Button(KeyCode::Space.into(), true));
+    input.handle(InputEvent::Button(MouseButton::Left.into(), false));
+    assert!(input.action("jump"));
+    input.clear();
+    input.handle(InputEvent::Button(KeyCode::Space.into(), false));
+    assert!(!input.action("jump") && input.action_just_released("jump"));
+  }
+
+  #[test]
+  fn focus_lost_releases() {
+    let world = world();
+    let input = world.get_resource_mut::<Input>().unwrap();
+    input.handle(InputEvent::Button(KeyCode::KeyW.into(), true));
+    input.handle(InputEvent::Button(MouseButton::Right.into(), true));
+    input.clear();
+
+    world.add_resource(WindowEvent::Focused(false));
+    world.run_system(stage::EVENT);
+    assert!(!input.pressed(KeyCode::KeyW) && !input.pressed(MouseButton::Right));
+    assert!(input.just_released(KeyCode::KeyW) && input.just_released(MouseButton::Right));
+  }
+}
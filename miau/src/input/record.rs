@@ -0,0 +1,169 @@
+use std::fs::{File, self};
+use std::io::Write;
+use std::time::Duration;
+use crate::Result;
+use crate::ecs::World;
+use crate::input::{Input, InputEvent};
+
+pub struct Recorder {
+  frame: u64,
+  mode: Mode,
+}
+
+enum Mode {
+  Record {
+    file: File,
+    pending: Vec<(u64, InputEvent)>,
+  },
+  Replay {
+    events: Vec<(u64, InputEvent)>,
+    next: usize,
+    delta: Duration,
+  },
+}
+
+impl Recorder {
+  pub fn record(path: &str) -> Result<Self> {
+    Ok(Self {
+      frame: 0,
+      mode: Mode::Record {
+        file: File::create(path)?,
+        pending: vec![],
+      },
+    })
+  }
+
+  pub fn replay(path: &str, delta: Duration) -> Result<Self> {
+    let data = fs::read(path)?;
+    let mut data = data.as_slice();
+    let mut events = vec![];
+    while !data.is_empty() {
+      events.push(bincode::deserialize_from(&mut data)?);
+    }
+    Ok(Self {
+      frame: 0,
+      mode: Mode::Replay {
+        events,
+        next: 0,
+        delta,
+      },
+    })
+  }
+
+  pub fn frame(&self) -> u64 {
+    self.frame
+  }
+
+  pub fn replaying(&self) -> bool {
+    matches!(self.mode, Mode::Replay { .. })
+  }
+
+  pub fn finished(&self) -> bool {
+    match &self.mode {
+      Mode::Record { .. } => false,
+      Mode::Replay { events, next, .. } => *next >= events.len(),
+    }
+  }
+
+  pub fn delta(&self) -> Option<Duration> {
+    match self.mode {
+      Mode::Record { .. } => None,
+      Mode::Replay { delta, .. } => Some(delta),
+    }
+  }
+
+  pub(crate) fn push(&mut self, event: InputEvent) {
+    if let Mode::Record { pending, .. } = &mut self.mode {
+      pending.push((self.frame, event));
+    }
+  }
+
+  pub(crate) fn pre(world: &World) -> Result {
+    let recorder = world.get_resource_mut::<Recorder>().unwrap();
+    if let Mode::Replay { events, next, .. } = &mut recorder.mode {
+      let input = world.get_resource_mut::<Input>().unwrap();
+      while let Some((_, e)) = events.get(*next).filter(|e| e.0 <= recorder.frame) {
+        input.handle(*e);
+        *next += 1;
+      }
+    }
+    Ok(())
+  }
+
+  pub(crate) fn post(world: &World) -> Result {
+    let recorder = world.get_resource_mut::<Recorder>().unwrap();
+    if let Mode::Record { file, pending } = &mut recorder.mode {
+      for e in pending.drain(..) {
+        bincode::serialize_into(&mut *file, &e)?;
+      }
+      file.flush()?;
+    }
+    recorder.frame += 1;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use glam::Vec2;
+  use crate::ecs::stage;
+  use crate::input::KeyCode;
+
+  fn world(recorder: Recorder) -> World {
+    let world = World::new();
+    world.add_resource(recorder);
+    Input::init(&world).unwrap();
+    world
+  }
+
+  // sends the live events for one frame and returns what the game would see
+  fn frame(world: &World, events: &[InputEvent]) -> (bool, Vec2, Vec2) {
+    world.run_system(stage::PRE_UPDATE);
+    for e in events {
+      Input::send(world, *e);
+    }
+    let input = world.get_resource_mut::<Input>().unwrap();
+    let seen = (
+      input.pressed(KeyCode::KeyW),
+      input.cursor(),
+      input.mouse_motion(),
+    );
+    world.run_system(stage::POST_DRAW);
+    input.clear();
+    seen
+  }
+
+  #[test]
+  fn record_replay() {
+    let path = std::env::temp_dir().join(format!("miau_record_{}.bin", std::process::id()));
+    let path = path.to_str().unwrap();
+    let frames = [
+      vec![InputEvent::Button(KeyCode::KeyW.into(), true)],
+      vec![],
+      vec![
+        InputEvent::MouseMotion(Vec2::new(3.0, -1.0)),
+        InputEvent::CursorMoved(Vec2::new(10.0, 20.0)),
+        InputEvent::MouseMotion(Vec2::new(1.0, 1.0)),
+      ],
+      vec![InputEvent::Button(KeyCode::KeyW.into(), false)],
+    ];
+
+    let recording = world(Recorder::record(path).unwrap());
+    let recorded: Vec<_> = frames.iter().map(|f| frame(&recording, f)).collect();
+    drop(recording);
+
+    // live input during a replay is ignored
+    let live = [InputEvent::MouseMotion(Vec2::new(100.0, 100.0))];
+    let replay = world(Recorder::replay(path, Duration::from_millis(16)).unwrap());
+    let replayed: Vec<_> = frames.iter().map(|_| frame(&replay, &live)).collect();
+    assert!(replay.get_resource::<Recorder>().unwrap().finished());
+    fs::remove_file(path).unwrap();
+
+    assert_eq!(recorded, replayed);
+    assert_eq!(
+      recorded[2],
+      (true, Vec2::new(10.0, 20.0), Vec2::new(4.0, 0.0))
+    );
+  }
+}
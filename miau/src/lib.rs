@@ -11,18 +11,29 @@ pub mod ecs;
 pub mod assets;
 pub mod scene;
 pub mod ui;
+pub mod input;
+pub mod time;
+pub mod window;
 
 use std::mem::MaybeUninit;
 use std::any::Any;
-use winit::window::{WindowBuilder, Window};
+use winit::window::Window;
 use winit::event_loop::EventLoop;
-use winit::event::{Event, WindowEvent};
+use winit::event::{Event, WindowEvent, DeviceEvent};
+use glam::Vec2;
 use crate::gfx::Renderer;
 use crate::ecs::{World, System, stage};
 use crate::assets::Assets;
+use crate::input::{Input, InputEvent};
+use crate::time::{Time, FixedTime};
+use crate::window::WindowSettings;
 
 #[doc(hidden)]
 pub use erased_serde;
+#[doc(hidden)]
+pub use wgpu;
+#[doc(hidden)]
+pub use std::sync::OnceLock;
 pub use glam as math;
 
 pub type Result<T = (), E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
@@ -54,11 +65,20 @@ impl Engine {
 
 fn init(world: &World) -> Result {
   let event_loop = EventLoop::new()?;
-  let window = WindowBuilder::new().build(&event_loop)?;
+  if world.get_resource::<WindowSettings>().is_none() {
+    world.add_resource(WindowSettings::new());
+  }
+  let window = world
+    .get_resource::<WindowSettings>()
+    .unwrap()
+    .build(&event_loop)?;
   world.add_resource(event_loop);
   world.add_resource(window);
   Assets::init(world)?;
   pollster::block_on(Renderer::init(world))?;
+  WindowSettings::init(world)?;
+  Time::init(world)?;
+  Input::init(world)?;
   world.add_system(stage::START, start);
   world.add_system(stage::UPDATE, update);
   Ok(())
@@ -71,7 +91,11 @@ fn start(world: &World) -> Result {
     .run(move |event, elwt| match event {
       Event::WindowEvent { event, .. } => {
         match event {
-          WindowEvent::RedrawRequested => world.run_system(stage::UPDATE),
+          WindowEvent::RedrawRequested => {
+            world.run_system(stage::PRE_UPDATE);
+            FixedTime::run(world);
+            world.run_system(stage::UPDATE);
+          }
           WindowEvent::Resized(size) => world.get_resource_mut::<Renderer>().unwrap().resize(size),
           WindowEvent::CloseRequested => elwt.exit(),
           _ => {}
@@ -79,6 +103,13 @@ fn start(world: &World) -> Result {
         world.add_resource(event);
         world.run_system(stage::EVENT);
       }
+      Event::DeviceEvent {
+        event: DeviceEvent::MouseMotion { delta },
+        ..
+      } => Input::send(
+        world,
+        InputEvent::MouseMotion(Vec2::new(delta.0 as _, delta.1 as _)),
+      ),
       Event::AboutToWait => world.get_resource::<Window>().unwrap().request_redraw(),
       _ => {}
     })?;
@@ -86,7 +117,8 @@ fn start(world: &World) -> Result {
 }
 
 fn update(world: &World) -> Result {
-  world.get_resource_mut::<Renderer>().unwrap().frame(world);
+  world.get_resource_mut::<Renderer>().unwrap().frame(world)?;
+  world.get_resource_mut::<Input>().unwrap().clear();
   Ok(())
 }
 
@@ -1,11 +1,23 @@
 use glam::{Vec3, Quat, EulerRot, Mat4};
 use serde::{Serialize, Deserialize};
-use crate::ecs::component;
+use crate::Result;
+use crate::ecs::{World, Entity, component};
+use crate::assets::asset;
+use crate::time::FixedTime;
 
 pub use crate::gfx::standard::Model;
+pub use crate::gfx::material::{Material, MaterialDesc, AlphaMode};
+pub use crate::gfx::light::{DirectionalLight, PointLight, SpotLight, AmbientLight};
+pub use crate::gfx::shadow::{ShadowCaster, ShadowPass};
+pub use crate::gfx::gltf::Gltf;
+pub use crate::gfx::skybox::{Skybox, Cubemap};
+pub use crate::gfx::post::{PostProcess, Tonemap};
+pub use crate::gfx::bounds::Bounds;
+pub use crate::gfx::gizmos::Gizmos;
+pub use crate::gfx::Camera;
 
 #[component]
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct Transform {
   pub position: Vec3,
   pub rotation: Quat,
@@ -41,12 +53,112 @@ impl Transform {
     self
   }
 
+  // points +z at target
+  pub fn look_at(mut self, target: Vec3, up: Vec3) -> Self {
+    self.rotation = Quat::from_mat4(&Mat4::look_at_lh(self.position, target, up).inverse());
+    self
+  }
+
   pub fn scale(mut self, scale: Vec3) -> Self {
     self.scale = scale;
     self
   }
 
+  pub fn from_mat4(m: Mat4) -> Self {
+    let (scale, rotation, position) = m.to_scale_rotation_translation();
+    Self {
+      position,
+      rotation,
+      scale,
+    }
+  }
+
   pub fn as_mat4(&self) -> Mat4 {
     Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position)
   }
+
+  pub fn lerp(&self, rhs: &Self, t: f32) -> Self {
+    Self {
+      position: self.position.lerp(rhs.position, t),
+      rotation: self.rotation.slerp(rhs.rotation, t),
+      scale: self.scale.lerp(rhs.scale, t),
+    }
+  }
+
+  pub fn model_matrix(world: &World, e: &Entity) -> Option<Mat4> {
+    let t = e.get_one::<Transform>()?;
+    Some(match e.get_one::<Interpolate>().and_then(|i| i.0) {
+      Some(prev) => prev
+        .lerp(&t, world.get_resource::<FixedTime>().unwrap().alpha())
+        .as_mat4(),
+      None => t.as_mat4(),
+    })
+  }
+}
+
+#[component]
+#[derive(Serialize, Deserialize)]
+pub struct Interpolate(Option<Transform>);
+
+impl Interpolate {
+  pub fn new() -> Self {
+    Self(None)
+  }
+
+  pub(crate) fn store(world: &World) {
+    for (e, mut i) in world.get_mut::<Interpolate>() {
+      i.0 = e.get_one::<Transform>().map(|t| *t);
+    }
+  }
+}
+
+#[asset(Prefab::load)]
+#[derive(Serialize, Deserialize)]
+pub struct Prefab {
+  pub nodes: Vec<PrefabNode>,
+  pub roots: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PrefabNode {
+  pub name: Option<String>,
+  pub transform: Transform,
+  pub models: Vec<Model>,
+  pub children: Vec<usize>,
+}
+
+impl Prefab {
+  fn load(data: &[u8]) -> Result<Self> {
+    Ok(serde_json::from_slice(data)?)
+  }
+
+  // there are no parent links, nodes are spawned with their transforms flattened into world
+  // space, so shear from non-uniform scale under a rotated child is lost
+  pub fn spawn<'w>(&self, world: &'w World, root: Transform) -> Vec<Entity<'w>> {
+    let mut entities = vec![];
+    for i in &self.roots {
+      self.spawn_node(world, *i, root.as_mat4(), &mut entities);
+    }
+    entities
+  }
+
+  fn spawn_node<'w>(
+    &self,
+    world: &'w World,
+    i: usize,
+    parent: Mat4,
+    entities: &mut Vec<Entity<'w>>,
+  ) {
+    let node = &self.nodes[i];
+    let m = parent * node.transform.as_mat4();
+    let e = world.spawn();
+    e.insert(Transform::from_mat4(m));
+    for model in &node.models {
+      e.insert(model.clone());
+    }
+    entities.push(e);
+    for c in &node.children {
+      self.spawn_node(world, *c, m, entities);
+    }
+  }
 }
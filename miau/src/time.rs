@@ -0,0 +1,298 @@
+use std::time::{Instant, Duration};
+use serde::{Serialize, Deserialize};
+use crate::Result;
+use crate::ecs::{World, stage, component};
+use crate::gfx::DeltaTime;
+use crate::input::record::Recorder;
+use crate::scene::{Interpolate, Gizmos};
+
+pub struct Time {
+  last: Instant,
+  raw_delta: Duration,
+  raw_elapsed: Duration,
+  delta: Duration,
+  elapsed: Duration,
+  frame: u64,
+  pub scale: f32,
+  pub paused: bool,
+}
+
+impl Time {
+  pub fn init(world: &World) -> Result {
+    world.add_resource(Self {
+      last: Instant::now(),
+      raw_delta: Duration::ZERO,
+      raw_elapsed: Duration::ZERO,
+      delta: Duration::ZERO,
+      elapsed: Duration::ZERO,
+      frame: 0,
+      scale: 1.0,
+      paused: false,
+    });
+    if world.get_resource::<FixedTime>().is_none() {
+      world.add_resource(FixedTime::from_hz(60.0)?);
+    }
+    world.add_system(stage::PRE_UPDATE, Self::tick);
+    Ok(())
+  }
+
+  fn tick(world: &World) -> Result {
+    let time = world.get_resource_mut::<Time>().unwrap();
+    let now = Instant::now();
+    time.raw_delta = world
+      .get_resource::<Recorder>()
+      .and_then(Recorder::delta)
+      .unwrap_or(now - time.last);
+    time.last = now;
+    time.delta = if time.paused {
+      Duration::ZERO
+    } else {
+      time.raw_delta.mul_f32(time.scale)
+    };
+    time.raw_elapsed += time.raw_delta;
+    time.elapsed += time.delta;
+    time.frame += 1;
+    world.add_resource(DeltaTime(time.raw_delta));
+    world.get_resource_mut::<FixedTime>().unwrap().accumulator += time.delta;
+
+    for (_, mut t) in world.get_mut::<Timer>() {
+      t.tick(time.delta);
+    }
+    for (_, mut s) in world.get_mut::<Stopwatch>() {
+      s.tick(time.delta);
+    }
+    Ok(())
+  }
+
+  pub fn delta(&self) -> Duration {
+    self.delta
+  }
+
+  pub fn delta_secs(&self) -> f32 {
+    self.delta.as_secs_f32()
+  }
+
+  pub fn raw_delta(&self) -> Duration {
+    self.raw_delta
+  }
+
+  pub fn elapsed(&self) -> Duration {
+    self.elapsed
+  }
+
+  pub fn elapsed_secs(&self) -> f32 {
+    self.elapsed.as_secs_f32()
+  }
+
+  pub fn raw_elapsed(&self) -> Duration {
+    self.raw_elapsed
+  }
+
+  pub fn frame(&self) -> u64 {
+    self.frame
+  }
+}
+
+pub struct FixedTime {
+  pub step: Duration,
+  pub max_steps: u32,
+  accumulator: Duration,
+  alpha: f32,
+}
+
+impl FixedTime {
+  pub fn new(step: Duration) -> Result<Self> {
+    if step.is_zero() {
+      return Err("fixed time step must be greater than zero".into());
+    }
+    Ok(Self {
+      step,
+      max_steps: 8,
+      accumulator: Duration::ZERO,
+      alpha: 0.0,
+    })
+  }
+
+  pub fn from_hz(hz: f64) -> Result<Self> {
+    let invalid = || format!("invalid fixed update rate {} Hz", hz);
+    if !hz.is_finite() || hz <= 0.0 {
+      return Err(invalid().into());
+    }
+    Self::new(Duration::try_from_secs_f64(1.0 / hz).map_err(|_| invalid())?)
+  }
+
+  pub(crate) fn run(world: &World) {
+    // step is public, a zero one would spin max_steps times a frame
+    if world.get_resource::<FixedTime>().unwrap().step.is_zero() {
+      return;
+    }
+    let mut steps = 0;
+    loop {
+      let fixed = world.get_resource_mut::<FixedTime>().unwrap();
+      if fixed.accumulator < fixed.step {
+        break;
+      }
+      if steps == fixed.max_steps {
+        fixed.accumulator = Duration::ZERO;
+        break;
+      }
+      fixed.accumulator -= fixed.step;
+      steps += 1;
+      Interpolate::store(world);
+      if let Some(gizmos) = world.get_resource_mut::<Gizmos>() {
+        gizmos.begin_fixed();
+      }
+      world.run_system(stage::FIXED_UPDATE);
+      if let Some(gizmos) = world.get_resource_mut::<Gizmos>() {
+        gizmos.end_fixed();
+      }
+    }
+    let fixed = world.get_resource_mut::<FixedTime>().unwrap();
+    fixed.alpha = fixed.accumulator.as_secs_f32() / fixed.step.as_secs_f32();
+  }
+
+  pub fn step_secs(&self) -> f32 {
+    self.step.as_secs_f32()
+  }
+
+  pub fn alpha(&self) -> f32 {
+    self.alpha
+  }
+}
+
+#[component]
+#[derive(Serialize, Deserialize)]
+pub struct Timer {
+  pub duration: Duration,
+  pub repeat: bool,
+  pub paused: bool,
+  elapsed: Duration,
+  finished: bool,
+  times_finished: u32,
+}
+
+impl Timer {
+  pub fn new(duration: Duration, repeat: bool) -> Self {
+    Self {
+      duration,
+      repeat,
+      paused: false,
+      elapsed: Duration::ZERO,
+      finished: false,
+      times_finished: 0,
+    }
+  }
+
+  pub fn once(duration: Duration) -> Self {
+    Self::new(duration, false)
+  }
+
+  pub fn repeating(duration: Duration) -> Self {
+    Self::new(duration, true)
+  }
+
+  pub fn tick(&mut self, delta: Duration) {
+    self.times_finished = 0;
+    if self.repeat {
+      self.finished = false;
+    }
+    if self.paused || self.finished {
+      return;
+    }
+    self.elapsed += delta;
+    if self.elapsed >= self.duration {
+      self.finished = true;
+      if self.repeat && !self.duration.is_zero() {
+        self.times_finished = (self.elapsed.as_nanos() / self.duration.as_nanos()) as _;
+        self.elapsed =
+          Duration::from_nanos((self.elapsed.as_nanos() % self.duration.as_nanos()) as _);
+      } else {
+        self.times_finished = 1;
+        self.elapsed = self.duration;
+      }
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.elapsed = Duration::ZERO;
+    self.finished = false;
+    self.times_finished = 0;
+  }
+
+  pub fn elapsed(&self) -> Duration {
+    self.elapsed
+  }
+
+  pub fn fraction(&self) -> f32 {
+    if self.duration.is_zero() {
+      1.0
+    } else {
+      self.elapsed.as_secs_f32() / self.duration.as_secs_f32()
+    }
+  }
+
+  pub fn finished(&self) -> bool {
+    self.finished
+  }
+
+  pub fn just_finished(&self) -> bool {
+    self.times_finished > 0
+  }
+
+  pub fn times_finished(&self) -> u32 {
+    self.times_finished
+  }
+}
+
+#[component]
+#[derive(Serialize, Deserialize)]
+pub struct Stopwatch {
+  pub paused: bool,
+  elapsed: Duration,
+}
+
+impl Stopwatch {
+  pub fn new() -> Self {
+    Self {
+      paused: false,
+      elapsed: Duration::ZERO,
+    }
+  }
+
+  pub fn tick(&mut self, delta: Duration) {
+    if !self.paused {
+      self.elapsed += delta;
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.elapsed = Duration::ZERO;
+  }
+
+  pub fn elapsed(&self) -> Duration {
+    self.elapsed
+  }
+
+  pub fn elapsed_secs(&self) -> f32 {
+    self.elapsed.as_secs_f32()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn fixed_step() {
+    assert!(FixedTime::new(Duration::ZERO).is_err());
+    assert!(FixedTime::from_hz(0.0).is_err());
+    assert!(FixedTime::from_hz(-30.0).is_err());
+    assert!(FixedTime::from_hz(f64::NAN).is_err());
+    assert!(FixedTime::from_hz(f64::INFINITY).is_err());
+    assert!(FixedTime::from_hz(1e-300).is_err());
+    assert_eq!(
+      FixedTime::from_hz(50.0).unwrap().step,
+      Duration::from_millis(20)
+    );
+  }
+}
@@ -1,4 +1,5 @@
 use std::mem;
+use std::rc::Rc;
 use winit::window::{Window, CursorIcon};
 use winit::event::{WindowEvent, ElementState, MouseButton, MouseScrollDelta, TouchPhase};
 use winit::keyboard::{PhysicalKey, KeyCode};
@@ -10,13 +11,18 @@ use imgui::{
 use log::info;
 use crate::Result;
 use crate::ecs::{World, stage};
-use crate::gfx::{Renderer, Shader, Texture, Frame, Binding, SceneConst, DeltaTime, FORMAT, cast_slice};
+use crate::gfx::{
+  Bindable, Renderer, Shader, Texture, Frame, Binding, SceneConst, DeltaTime, PipelineBuilder,
+  cast_slice,
+};
+use crate::gfx::graph::{RenderGraph, Pass};
+use crate::gfx::reload::HotReload;
 use crate::assets::Assets;
 
 pub use imgui;
 
 pub struct UiPass {
-  pipeline: wgpu::RenderPipeline,
+  pipeline: Rc<wgpu::RenderPipeline>,
   ctx: Context,
   textures: Textures<Texture>,
   vert_buf: wgpu::Buffer,
@@ -30,41 +36,13 @@ impl UiPass {
       .get_resource::<Assets>()
       .unwrap()
       .load::<Shader>("miau_shaders.spv")?;
-    let pipeline_layout = renderer
-      .device
-      .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        bind_group_layouts: &[&renderer.scene_layout, &renderer.tex_layout],
-        push_constant_ranges: &[],
-        label: None,
-      });
-    let pipeline = renderer
-      .device
-      .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-          module: &shader.0,
-          entry_point: "ui_v",
-          buffers: &[wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<DrawVert>() as _,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Unorm8x4],
-          }],
-        },
-        fragment: Some(wgpu::FragmentState {
-          module: &shader.0,
-          entry_point: "ui_f",
-          targets: &[Some(wgpu::ColorTargetState {
-            format: FORMAT,
-            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-            write_mask: wgpu::ColorWrites::ALL,
-          })],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
-        label: None,
-      });
+    let pipeline = Self::pipeline(world, &shader);
+    HotReload::watch(
+      world,
+      "miau_shaders.spv",
+      Self::pipeline,
+      |p: &mut Self, pipeline| p.pipeline = pipeline,
+    );
     let mut ctx = Context::create();
     let mut textures = Textures::new();
     ctx.set_ini_filename(None);
@@ -115,7 +93,10 @@ impl UiPass {
     info!("Initialized ImGui {} context.", imgui::dear_imgui_version());
 
     world.add_system(stage::PRE_DRAW, Self::pre);
-    world.add_system(stage::POST_DRAW, Self::pass);
+    world
+      .get_resource_mut::<RenderGraph>()
+      .unwrap()
+      .add(Pass::new("ui", Self::pass).write("surface").after("post"));
     world.add_system(stage::EVENT, Self::event);
     Ok(Self {
       pipeline,
@@ -132,6 +113,24 @@ impl UiPass {
     Ok(())
   }
 
+  fn pipeline(world: &World, shader: &Shader) -> Rc<wgpu::RenderPipeline> {
+    let renderer = world.get_resource::<Renderer>().unwrap();
+    PipelineBuilder::new(shader, "ui_v", "ui_f")
+      .layout(SceneConst::get_layout(world))
+      .layout(&renderer.tex_layout)
+      .no_mesh()
+      .buffer(wgpu::VertexBufferLayout {
+        array_stride: mem::size_of::<DrawVert>() as _,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Unorm8x4],
+      })
+      .format(renderer.format)
+      .blend(wgpu::BlendState::ALPHA_BLENDING)
+      .no_depth()
+      .samples(1)
+      .build(world)
+  }
+
   fn pass(world: &World) -> Result {
     let renderer = world.get_resource::<Renderer>().unwrap();
     let frame = world.get_resource_mut::<Frame>().unwrap();
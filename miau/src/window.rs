@@ -0,0 +1,151 @@
+use winit::window::{WindowBuilder, Window, Fullscreen, CursorGrabMode};
+use winit::event_loop::EventLoop;
+use winit::monitor::MonitorHandle;
+use winit::dpi::LogicalSize;
+use log::warn;
+use crate::Result;
+use crate::ecs::{World, stage};
+use crate::gfx::Renderer;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WindowMode {
+  Windowed,
+  Borderless,
+  Fullscreen,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct WindowSettings {
+  pub title: String,
+  pub width: u32,
+  pub height: u32,
+  pub mode: WindowMode,
+  pub resizable: bool,
+  pub cursor_grab: bool,
+  pub present_mode: wgpu::PresentMode,
+}
+
+struct Applied(WindowSettings);
+
+impl WindowSettings {
+  pub fn new() -> Self {
+    Self {
+      title: "miau".to_string(),
+      width: 1280,
+      height: 720,
+      mode: WindowMode::Windowed,
+      resizable: true,
+      cursor_grab: false,
+      present_mode: wgpu::PresentMode::AutoVsync,
+    }
+  }
+
+  pub fn title(mut self, title: &str) -> Self {
+    self.title = title.to_string();
+    self
+  }
+
+  pub fn size(mut self, width: u32, height: u32) -> Self {
+    self.width = width;
+    self.height = height;
+    self
+  }
+
+  pub fn mode(mut self, mode: WindowMode) -> Self {
+    self.mode = mode;
+    self
+  }
+
+  pub fn resizable(mut self, resizable: bool) -> Self {
+    self.resizable = resizable;
+    self
+  }
+
+  pub fn cursor_grab(mut self, cursor_grab: bool) -> Self {
+    self.cursor_grab = cursor_grab;
+    self
+  }
+
+  pub fn present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+    self.present_mode = present_mode;
+    self
+  }
+
+  pub(crate) fn build(&self, event_loop: &EventLoop<()>) -> Result<Window> {
+    let window = WindowBuilder::new()
+      .with_title(&self.title)
+      .with_inner_size(LogicalSize::new(self.width, self.height))
+      .with_resizable(self.resizable)
+      .with_fullscreen(self.fullscreen(event_loop.primary_monitor()))
+      .build(event_loop)?;
+    set_cursor_grab(&window, self.cursor_grab);
+    Ok(window)
+  }
+
+  pub(crate) fn init(world: &World) -> Result {
+    let settings = world.get_resource::<WindowSettings>().unwrap();
+    world.add_resource(Applied(settings.clone()));
+    world.add_system(stage::PRE_UPDATE, Self::apply);
+    Ok(())
+  }
+
+  fn apply(world: &World) -> Result {
+    let settings = world.get_resource::<WindowSettings>().unwrap();
+    let applied = &mut world.get_resource_mut::<Applied>().unwrap().0;
+    if settings == applied {
+      return Ok(());
+    }
+    let window = world.get_resource::<Window>().unwrap();
+    if settings.title != applied.title {
+      window.set_title(&settings.title);
+    }
+    if (settings.width, settings.height) != (applied.width, applied.height) {
+      let _ = window.request_inner_size(LogicalSize::new(settings.width, settings.height));
+    }
+    if settings.mode != applied.mode {
+      window.set_fullscreen(settings.fullscreen(window.current_monitor()));
+    }
+    if settings.resizable != applied.resizable {
+      window.set_resizable(settings.resizable);
+    }
+    if settings.cursor_grab != applied.cursor_grab {
+      set_cursor_grab(window, settings.cursor_grab);
+    }
+    if settings.present_mode != applied.present_mode {
+      let renderer = world.get_resource_mut::<Renderer>().unwrap();
+      renderer.set_present_mode(settings.present_mode);
+      renderer.resize(window.inner_size());
+    }
+    *applied = settings.clone();
+    Ok(())
+  }
+
+  fn fullscreen(&self, monitor: Option<MonitorHandle>) -> Option<Fullscreen> {
+    match self.mode {
+      WindowMode::Windowed => None,
+      WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+      // the largest resolution at its highest refresh rate
+      WindowMode::Fullscreen => monitor
+        .and_then(|m| {
+          m.video_modes().max_by_key(|v| {
+            let size = v.size();
+            (size.width * size.height, v.refresh_rate_millihertz())
+          })
+        })
+        .map(Fullscreen::Exclusive),
+    }
+  }
+}
+
+// best effort, some platforms support neither confining nor locking the cursor
+fn set_cursor_grab(window: &Window, grab: bool) {
+  let result = match grab {
+    true => window
+      .set_cursor_grab(CursorGrabMode::Confined)
+      .or_else(|_| window.set_cursor_grab(CursorGrabMode::Locked)),
+    false => window.set_cursor_grab(CursorGrabMode::None),
+  };
+  if let Err(e) = result {
+    warn!("Could not set the cursor grab: {}", e);
+  }
+}
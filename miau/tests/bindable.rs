@@ -0,0 +1,5 @@
+#[test]
+fn bindable() {
+  let t = trybuild::TestCases::new();
+  t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,9 @@
+use miau::gfx::Bindable;
+
+#[derive(Bindable)]
+#[repr(C)]
+struct Weights {
+  weights: [f32; 8],
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use miau::gfx::Bindable;
+use miau::math::Vec4;
+
+#[derive(Bindable)]
+struct Tint {
+  color: Vec4,
+}
+
+fn main() {}
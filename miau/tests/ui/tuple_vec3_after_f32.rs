@@ -0,0 +1,8 @@
+use miau::gfx::Bindable;
+use miau::math::Vec3;
+
+#[derive(Bindable)]
+#[repr(C)]
+struct Light(f32, Vec3);
+
+fn main() {}
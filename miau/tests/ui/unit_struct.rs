@@ -0,0 +1,7 @@
+use miau::gfx::Bindable;
+
+#[derive(Bindable)]
+#[repr(C)]
+struct Empty;
+
+fn main() {}
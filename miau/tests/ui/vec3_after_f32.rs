@@ -0,0 +1,11 @@
+use miau::gfx::Bindable;
+use miau::math::Vec3;
+
+#[derive(Bindable)]
+#[repr(C)]
+struct Light {
+  intensity: f32,
+  color: Vec3,
+}
+
+fn main() {}